@@ -12,7 +12,8 @@ use matchbox_signaling::{
         full_mesh::{FullMesh, FullMeshCallbacks, FullMeshState},
         SignalingTopology,
     },
-    Error, SignalingCallbacks, SignalingServer, SignalingServerBuilder, SignalingState,
+    Error, ShutdownHandle, SignalingCallbacks, SignalingServer, SignalingServerBuilder,
+    SignalingState,
 };
 
 /// A [`SignalingServer`] as a [`Resource`].
@@ -64,7 +65,10 @@ use matchbox_signaling::{
 /// }
 /// ```
 #[derive(Debug, Resource)]
-pub struct MatchboxServer(Task<Result<(), Error>>);
+pub struct MatchboxServer {
+    task: Task<Result<(), Error>>,
+    shutdown: ShutdownHandle,
+}
 
 impl<Topology, Cb, S> From<SignalingServerBuilder<Topology, Cb, S>> for MatchboxServer
 where
@@ -80,8 +84,9 @@ where
 impl From<SignalingServer> for MatchboxServer {
     fn from(server: SignalingServer) -> Self {
         let task_pool = IoTaskPool::get();
+        let shutdown = server.shutdown_handle();
         let task = task_pool.spawn(server.serve());
-        MatchboxServer(task)
+        MatchboxServer { task, shutdown }
     }
 }
 
@@ -128,13 +133,15 @@ struct StopServer;
 
 impl Command for StopServer {
     fn write(self, world: &mut bevy::prelude::World) {
-        world.remove_resource::<MatchboxServer>();
+        if let Some(server) = world.remove_resource::<MatchboxServer>() {
+            server.shutdown();
+        }
     }
 }
 
 /// A [`Commands`] extension used to stop a [`MatchboxServer`].
 pub trait StopServerExt {
-    /// Delete the [`MatchboxServer`] resource.
+    /// Gracefully shut down the [`MatchboxServer`] and delete the resource.
     fn stop_server(&mut self);
 }
 
@@ -145,6 +152,20 @@ impl<'w, 's> StopServerExt for Commands<'w, 's> {
 }
 
 impl MatchboxServer {
+    /// Gracefully shuts down the server, letting it close the websockets of connected peers in
+    /// the background.
+    ///
+    /// Removing the resource without calling this drops the connections without telling peers.
+    pub fn shutdown(self) {
+        self.shutdown.shutdown();
+        self.task.detach();
+    }
+
+    /// Returns a handle to gracefully shut down the server, see [`ShutdownHandle`]
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
     /// Creates a new builder for a [`SignalingServer`] with full-mesh topology.
     pub fn full_mesh_builder(
         socket_addr: impl Into<SocketAddr>,
@@ -101,11 +101,14 @@ impl<C: BuildablePlurality> From<(WebRtcSocket<C>, MessageLoopFuture)> for Match
 }
 
 /// A [`Command`] used to open a [`MatchboxSocket`] and allocate it as a resource.
-struct OpenSocket<C: BuildablePlurality>(WebRtcSocketBuilder<C>);
+///
+/// The socket is built as soon as the command is added, as custom signaller builders don't have
+/// to be [`Send`] on wasm.
+struct OpenSocket<C: BuildablePlurality>(MatchboxSocket<C>);
 
 impl<C: BuildablePlurality + 'static> Command for OpenSocket<C> {
     fn apply(self, world: &mut World) {
-        world.insert_resource(MatchboxSocket::from(self.0));
+        world.insert_resource(self.0);
     }
 }
 
@@ -117,7 +120,7 @@ pub trait OpenSocketExt<C: BuildablePlurality> {
 
 impl<'w, 's, C: BuildablePlurality + 'static> OpenSocketExt<C> for Commands<'w, 's> {
     fn open_socket(&mut self, socket_builder: WebRtcSocketBuilder<C>) {
-        self.add(OpenSocket(socket_builder))
+        self.add(OpenSocket(MatchboxSocket::from(socket_builder)))
     }
 }
 
@@ -149,6 +149,8 @@ fn lobby_system(
         match new_state {
             PeerState::Connected => info!("peer {peer} connected"),
             PeerState::Disconnected => info!("peer {peer} disconnected"),
+            PeerState::Connecting => info!("connecting to peer {peer}"),
+            PeerState::Failed { reason } => info!("could not connect to peer {peer}: {reason}"),
         }
     }
 
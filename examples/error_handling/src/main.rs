@@ -70,6 +70,12 @@ async fn async_main() {
                 PeerState::Disconnected => {
                     info!("Peer left: {peer}");
                 }
+                PeerState::Connecting => {
+                    info!("Connecting to peer: {peer}");
+                }
+                PeerState::Failed { reason } => {
+                    info!("Could not connect to {peer}: {reason}");
+                }
             }
         }
 
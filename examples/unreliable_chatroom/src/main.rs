@@ -2,7 +2,7 @@ use futures::{select, FutureExt};
 use futures_timer::Delay;
 use log::Level::Debug;
 use log::{debug, info};
-use matchbox_socket::{Packet, PeerState, SignalEvent, WebRtcSocket};
+use matchbox_socket::{Packet, PeerState, SignalEvent, SignalingEvent, WebRtcSocket};
 #[cfg(target_arch = "wasm32")]
 use std::future::pending;
 use std::time::Duration;
@@ -68,10 +68,27 @@ async fn async_main() {
                 PeerState::Disconnected => {
                     info!("Peer left: {peer}");
                 }
+                PeerState::Connecting => {
+                    info!("Connecting to peer: {peer}");
+                }
+                PeerState::Failed { reason } => {
+                    info!("Could not connect to {peer}: {reason}");
+                }
             }
         }
 
         for msg in socket.update_signals() {
+            let msg = match msg {
+                SignalingEvent::Server(msg) => msg,
+                SignalingEvent::Lost => {
+                    info!("Lost connection to the signaling server, reconnecting...");
+                    continue;
+                }
+                SignalingEvent::Restored => {
+                    info!("Reconnected to the signaling server");
+                    continue;
+                }
+            };
             match &msg {
                 SignalEvent::Peer(_) => {}
                 _ => debug!("Signal event: {msg:?}"),
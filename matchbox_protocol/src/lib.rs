@@ -9,6 +9,25 @@ use uuid::Uuid;
 )]
 pub struct PeerId(pub Uuid);
 
+/// A secret handed to a peer along with its [`PeerId`], see [`PeerEvent::ResumeToken`].
+///
+/// Presenting it when reconnecting to the same room resumes the session, so the peer keeps its
+/// [`PeerId`] instead of showing up as a new peer.
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, From, Hash)]
+pub struct ResumeToken(pub Uuid);
+
+/// The query parameter a client uses to present its [`ResumeToken`] when reconnecting
+pub const RESUME_TOKEN_QUERY_PARAM: &str = "resume_token";
+
+/// The query parameter a client sets to be sent a [`PeerEvent::ResumeToken`].
+///
+/// Older clients don't know the event, so the server only sends it to clients asking for it.
+pub const RESUMABLE_QUERY_PARAM: &str = "resumable";
+
+/// The query parameter a client uses to present an authentication token, for clients that can't
+/// set request headers, e.g. browsers
+pub const AUTH_TOKEN_QUERY_PARAM: &str = "token";
+
 /// Format for a room id
 #[derive(Debug, Serialize, Deserialize, Default, Clone, PartialEq, Eq, Hash)]
 pub struct RoomId(pub String);
@@ -16,8 +35,14 @@ pub struct RoomId(pub String);
 /// Requests go from peer to signaling server
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
 pub enum PeerRequest<S> {
-    Signal { receiver: PeerId, data: S },
+    Signal {
+        receiver: PeerId,
+        data: S,
+    },
     KeepAlive,
+    /// The peer is leaving the room for good, so the server can tell the other peers right away
+    /// instead of holding its session for a reconnect
+    Leave,
 }
 
 /// Events go from signaling server to peer
@@ -26,6 +51,9 @@ pub enum PeerEvent<S> {
     /// Sent by the server to the connecting peer, immediately after connection
     /// before any other events
     IdAssigned(PeerId),
+    /// Sent by the server right after [`PeerEvent::IdAssigned`] to peers connecting with
+    /// [`RESUMABLE_QUERY_PARAM`] or [`RESUME_TOKEN_QUERY_PARAM`], if sessions can be resumed
+    ResumeToken(ResumeToken),
     NewPeer(PeerId),
     PeerLeft(PeerId),
     Signal {
@@ -1,5 +1,5 @@
 use clap::Parser;
-use std::net::SocketAddr;
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
 
 #[derive(Parser, Debug)]
 #[clap(
@@ -10,4 +10,20 @@ use std::net::SocketAddr;
 pub struct Args {
     #[clap(default_value = "0.0.0.0:2053", env)]
     pub host: SocketAddr,
+
+    /// PEM encoded certificate chain to serve `wss://` with, reloaded when the file changes
+    #[clap(long, env, requires = "tls_key")]
+    pub tls_cert: Option<PathBuf>,
+
+    /// PEM encoded private key of the TLS certificate
+    #[clap(long, env, requires = "tls_cert")]
+    pub tls_key: Option<PathBuf>,
+
+    /// Seconds a disconnected peer can reconnect within and keep its id, disabled if not set
+    #[clap(long, env, value_parser = parse_seconds)]
+    pub session_resume_grace_period: Option<Duration>,
+}
+
+fn parse_seconds(secs: &str) -> Result<Duration, std::num::ParseIntError> {
+    secs.parse().map(Duration::from_secs)
 }
@@ -91,6 +91,21 @@ impl ServerState {
         prev_peers
     }
 
+    /// Replace the channel of a peer that resumed its session, returning whether the peer existed
+    pub fn resume_peer(
+        &mut self,
+        peer_id: PeerId,
+        sender: UnboundedSender<Result<Message, Error>>,
+    ) -> bool {
+        match self.clients.lock().unwrap().get_mut(&peer_id) {
+            Some(peer) => {
+                peer.sender = sender;
+                true
+            }
+            None => false,
+        }
+    }
+
     /// Get a peer
     pub fn get_peer(&self, peer_id: &PeerId) -> Option<Peer> {
         let clients = self.clients.lock().unwrap();
@@ -18,31 +18,48 @@ impl SignalingTopology<NoCallbacks, ServerState> for MatchmakingDemoTopology {
             peer_id,
             sender,
             mut receiver,
+            mut session,
             mut state,
             ..
         } = upgrade;
 
         let room = state.remove_waiting_peer(peer_id);
-        let peer = Peer {
-            uuid: peer_id,
-            sender: sender.clone(),
-            room,
-        };
+        // Other peers already know about a peer resuming its session, only swap the channel
+        if !(session.is_resumed() && state.resume_peer(peer_id, sender.clone())) {
+            let peer = Peer {
+                uuid: peer_id,
+                sender: sender.clone(),
+                room,
+            };
 
-        // Tell other waiting peers about me!
-        let peers = state.add_peer(peer);
-        let event_text = JsonSignalEvent::Peer(PeerEvent::NewPeer(peer_id)).to_string();
-        let event = Message::Text(event_text.clone());
-        for peer_id in peers {
-            if let Err(e) = state.try_send(peer_id, event.clone()) {
-                error!("error sending to {peer_id:?}: {e:?}");
-            } else {
-                info!("{peer_id} -> {event_text:?}");
+            // Tell other waiting peers about me!
+            let peers = state.add_peer(peer);
+            let event_text = JsonSignalEvent::Peer(PeerEvent::NewPeer(peer_id)).to_string();
+            let event = Message::Text(event_text.clone());
+            for peer_id in peers {
+                if let Err(e) = state.try_send(peer_id, event.clone()) {
+                    error!("error sending to {peer_id:?}: {e:?}");
+                } else {
+                    info!("{peer_id} -> {event_text:?}");
+                }
             }
         }
 
+        // Whether the peer left on purpose, instead of losing its connection
+        let mut left = false;
+
         // The state machine for the data channel established for this websocket.
-        while let Some(request) = receiver.next().await {
+        loop {
+            let request = tokio::select! {
+                request = receiver.next() => request,
+                _ = session.superseded() => {
+                    info!("Session of {peer_id:?} resumed on a new connection");
+                    return;
+                }
+            };
+            let Some(request) = request else {
+                break;
+            };
             let request = match parse_request(request) {
                 Ok(request) => request,
                 Err(e) => {
@@ -85,9 +102,20 @@ impl SignalingTopology<NoCallbacks, ServerState> for MatchmakingDemoTopology {
                     // Do nothing. KeepAlive packets are used to protect against idle websocket
                     // connections getting automatically disconnected, common for reverse proxies.
                 }
+                PeerRequest::Leave => {
+                    info!("{peer_id:?} left");
+                    left = true;
+                    break;
+                }
             }
         }
 
+        // Give the peer a chance to resume its session before telling everyone it left
+        if !left && receiver.may_resume() && session.hold().await {
+            info!("Session of {peer_id:?} resumed on a new connection");
+            return;
+        }
+
         // Peer disconnected or otherwise ended communication.
         info!("Removing peer: {:?}", peer_id);
         if let Some(removed_peer) = state.remove_peer(&peer_id) {
@@ -116,13 +144,19 @@ mod tests {
         ServerState,
     };
     use futures::{pin_mut, SinkExt, StreamExt};
-    use matchbox_protocol::{JsonSignalEvent, PeerEvent, PeerId};
-    use matchbox_signaling::{SignalingServer, SignalingServerBuilder};
+    use matchbox_protocol::{
+        JsonSignalEvent, PeerEvent, PeerId, RESUMABLE_QUERY_PARAM, RESUME_TOKEN_QUERY_PARAM,
+    };
+    use matchbox_signaling::{NoCallbacks, SignalingServer, SignalingServerBuilder};
     use std::{net::Ipv4Addr, str::FromStr, time::Duration};
     use tokio::{net::TcpStream, select, time};
     use tokio_tungstenite::{tungstenite::Message, MaybeTlsStream, WebSocketStream};
 
     fn app() -> SignalingServer {
+        app_builder().build()
+    }
+
+    fn app_builder() -> SignalingServerBuilder<MatchmakingDemoTopology, NoCallbacks, ServerState> {
         let mut state = ServerState::default();
         SignalingServerBuilder::new(
             (Ipv4Addr::LOCALHOST, 0),
@@ -149,7 +183,6 @@ mod tests {
                 state.assign_id_to_waiting_client(origin, peer_id);
             }
         })
-        .build()
     }
 
     // Helper to take the next PeerEvent from a stream
@@ -255,6 +288,62 @@ mod tests {
         assert_eq!(peer_left_event, JsonSignalEvent::Peer(PeerEvent::PeerLeft(b_uuid)));
     }
 
+    #[tokio::test]
+    async fn resume_session() {
+        let server = app_builder()
+            .session_resume_grace_period(Duration::from_secs(5))
+            .build();
+        let addr = server.local_addr();
+        tokio::spawn(server.serve());
+
+        let (mut client_a, _response) =
+            tokio_tungstenite::connect_async(format!("ws://{addr}/room_a"))
+                .await
+                .unwrap();
+
+        let a_uuid = get_peer_id(recv_peer_event(&mut client_a).await);
+
+        let (mut client_b, _response) = tokio_tungstenite::connect_async(format!(
+            "ws://{addr}/room_a?{RESUMABLE_QUERY_PARAM}=true"
+        ))
+        .await
+        .unwrap();
+
+        let b_uuid = get_peer_id(recv_peer_event(&mut client_b).await);
+        let b_token = match recv_peer_event(&mut client_b).await {
+            JsonSignalEvent::Peer(PeerEvent::ResumeToken(token)) => token,
+            event => panic!("Peer_event was not ResumeToken: {event:?}"),
+        };
+        let new_peer_event = recv_peer_event(&mut client_a).await;
+        assert_eq!(new_peer_event, JsonSignalEvent::Peer(PeerEvent::NewPeer(b_uuid)));
+
+        // Disconnect Peer B, and reconnect with its resume token
+        _ = client_b.close(None).await;
+        let (mut client_b, _response) = tokio_tungstenite::connect_async(format!(
+            "ws://{addr}/room_a?{RESUME_TOKEN_QUERY_PARAM}={b_token}"
+        ))
+        .await
+        .unwrap();
+        assert_eq!(get_peer_id(recv_peer_event(&mut client_b).await), b_uuid);
+        // Skip the resume token
+        _ = recv_peer_event(&mut client_b).await;
+
+        // Peer A wasn't told that B left, and signals reach B on its new connection
+        _ = client_a
+            .send(Message::text(format!(
+                "{{\"Signal\": {{\"receiver\": \"{b_uuid}\", \"data\": \"123\" }}}}"
+            )))
+            .await;
+        let signal_event = recv_peer_event(&mut client_b).await;
+        assert_eq!(
+            signal_event,
+            JsonSignalEvent::Peer(PeerEvent::Signal {
+                data: serde_json::Value::String("123".to_string()),
+                sender: a_uuid,
+            })
+        );
+    }
+
     #[tokio::test]
     async fn signal() {
         let server = app();
@@ -1,4 +1,4 @@
-use std::net::SocketAddr;
+use std::{net::SocketAddr, path::PathBuf};
 
 use clap::Parser;
 
@@ -11,4 +11,12 @@ use clap::Parser;
 pub struct Args {
     #[clap(default_value = "0.0.0.0:2053", env)]
     pub host: SocketAddr,
+
+    /// PEM encoded certificate chain to serve `wss://` with, reloaded when the file changes
+    #[clap(long, env, requires = "tls_key")]
+    pub tls_cert: Option<PathBuf>,
+
+    /// PEM encoded private key of the TLS certificate
+    #[clap(long, env, requires = "tls_cert")]
+    pub tls_key: Option<PathBuf>,
 }
@@ -1,6 +1,6 @@
 use axum::{http::StatusCode, response::IntoResponse, routing::get};
 use clap::Parser;
-use matchbox_signaling::SignalingServerBuilder;
+use matchbox_signaling::{SignalingServerBuilder, TlsConfig};
 use tracing::info;
 use tracing_subscriber::prelude::*;
 use matchbox_protocol::RoomId;
@@ -39,7 +39,7 @@ async fn main() {
     info!("Matchbox Signaling Server: {}", args.host);
 
     let mut state = ServerState::default();
-    let server = SignalingServerBuilder::new(args.host, MatchmakingDemoTopology, state.clone())
+    let builder = SignalingServerBuilder::new(args.host, MatchmakingDemoTopology, state.clone())
         .on_connection_request({
             let mut state = state.clone();
             move |connection| {
@@ -60,8 +60,15 @@ async fn main() {
         .mutate_router(|router| {
             // Apply router transformations
             router.route("/health", get(|| async { StatusCode::OK }))
-        })
-        .build();
+        });
+    let builder = match (args.tls_cert, args.tls_key) {
+        (Some(cert), Some(key)) => {
+            let tls = TlsConfig::from_pem_file(cert, key).expect("Unable to load TLS certificate");
+            builder.tls(tls)
+        }
+        _ => builder,
+    };
+    let server = builder.build();
     server
         .serve()
         .await
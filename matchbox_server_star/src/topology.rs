@@ -118,6 +118,10 @@ impl SignalingTopology<NoCallbacks, ServerState> for MatchmakingDemoTopology {
                     // Do nothing. KeepAlive packets are used to protect against idle websocket
                     // connections getting automatically disconnected, common for reverse proxies.
                 }
+                PeerRequest::Leave => {
+                    info!("{peer_id:?} left");
+                    break;
+                }
             }
         }
 
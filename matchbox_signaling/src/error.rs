@@ -10,4 +10,9 @@ pub enum Error {
     /// An error occurring from hyper
     #[error("Hyper error: {0}")]
     Hyper(#[from] hyper::Error),
+
+    /// An error loading the TLS certificate or serving TLS connections
+    #[cfg(feature = "tls")]
+    #[error("TLS error: {0}")]
+    Tls(String),
 }
@@ -11,8 +11,14 @@ pub use signaling_server::{
     builder::SignalingServerBuilder,
     callbacks::Callback,
     error::{ClientRequestError, SignalingError},
-    handlers::WsStateMeta,
+    handlers::{WsStateMeta, WsUpgradeMeta},
+    limits::{LimitExceeded, LimitViolation},
+    receiver::PeerReceiver,
     server::SignalingServer,
+    session::PeerSession,
+    shutdown::ShutdownHandle,
     NoCallbacks, NoState, SignalingCallbacks, SignalingState,
 };
+#[cfg(feature = "tls")]
+pub use signaling_server::tls::TlsConfig;
 pub use topologies::{common_logic, SignalingTopology};
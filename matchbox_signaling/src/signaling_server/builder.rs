@@ -2,14 +2,20 @@ use crate::{
     signaling_server::{
         callbacks::{Callback, SharedCallbacks},
         handlers::{ws_handler, WsUpgradeMeta},
+        heartbeat::Heartbeat,
+        limits::{ConnectionCounter, LimitViolation, Limits},
+        server::Listener,
+        session::SessionRegistry,
+        shutdown::shutdown_channel,
         NoCallbacks, NoState,
     },
     topologies::{SignalingStateMachine, SignalingTopology},
     SignalingCallbacks, SignalingServer, SignalingState,
 };
 use axum::{response::Response, routing::get, Extension, Router};
+use futures::Future;
 use matchbox_protocol::PeerId;
-use std::net::SocketAddr;
+use std::{net::SocketAddr, time::Duration};
 use tower_http::{
     cors::{Any, CorsLayer},
     trace::{DefaultOnResponse, TraceLayer},
@@ -17,6 +23,9 @@ use tower_http::{
 };
 use tracing::Level;
 
+#[cfg(feature = "tls")]
+use crate::signaling_server::tls::TlsConfig;
+
 /// Builder for [`SignalingServer`]s.
 ///
 /// Begin with [`SignalingServerBuilder::new`] and add parameters before calling
@@ -44,6 +53,19 @@ where
 
     /// Arbitrary state accompanying a server
     pub(crate) state: S,
+
+    /// How long to hold a dropped peer's session open for it to be resumed
+    pub(crate) session_resume_grace_period: Option<Duration>,
+
+    /// How peers are checked for liveness
+    pub(crate) heartbeat: Heartbeat,
+
+    /// Limits on connections and messages
+    pub(crate) limits: Limits,
+
+    /// The certificate to terminate TLS with, if any
+    #[cfg(feature = "tls")]
+    pub(crate) tls: Option<TlsConfig>,
 }
 
 impl<Topology, Cb, S> SignalingServerBuilder<Topology, Cb, S>
@@ -61,6 +83,11 @@ where
             callbacks: Cb::default(),
             topology,
             state,
+            session_resume_grace_period: None,
+            heartbeat: Heartbeat::default(),
+            limits: Limits::default(),
+            #[cfg(feature = "tls")]
+            tls: None,
         }
     }
 
@@ -80,6 +107,19 @@ where
         self
     }
 
+    /// Set an asynchronous callback triggered before websocket upgrade to determine if the
+    /// connection is allowed, e.g. by checking a token against an authentication service.
+    ///
+    /// The upgrade waits for the callback, without holding up other connections.
+    pub fn on_connection_request_async<F, Fut>(mut self, callback: F) -> Self
+    where
+        F: Fn(WsUpgradeMeta) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<bool, Response>> + Send + 'static,
+    {
+        self.shared_callbacks.on_connection_request = Callback::from_async(callback);
+        self
+    }
+
     /// Set a callback triggered when a socket has been assigned an ID. This happens after a
     /// connection is allowed, right before finalizing the websocket upgrade.
     pub fn on_id_assignment<F>(mut self, callback: F) -> Self
@@ -90,6 +130,108 @@ where
         self
     }
 
+    /// Set an asynchronous callback triggered when a socket has been assigned an ID. The websocket
+    /// upgrade is finalized once the callback completes.
+    pub fn on_id_assignment_async<F, Fut>(mut self, callback: F) -> Self
+    where
+        F: Fn((SocketAddr, PeerId)) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.shared_callbacks.on_id_assignment = Callback::from_async(callback);
+        self
+    }
+
+    /// Allow peers to resume their session after their websocket drops.
+    ///
+    /// Each peer is handed a resume token along with its ID. A peer that reconnects with its token
+    /// within the grace period keeps its ID, and other peers are not told that it left. Peers that
+    /// don't come back in time are removed as usual.
+    ///
+    /// Resuming is disabled by default.
+    pub fn session_resume_grace_period(mut self, grace_period: Duration) -> Self {
+        self.session_resume_grace_period = Some(grace_period);
+        self
+    }
+
+    /// Terminate TLS with the given certificate, so clients connect through `wss://` instead of
+    /// `ws://`.
+    #[cfg(feature = "tls")]
+    pub fn tls(mut self, config: TlsConfig) -> Self {
+        self.tls = Some(config);
+        self
+    }
+
+    /// Ping every peer at the given interval.
+    ///
+    /// Clients answer pings on their own, which keeps connections through proxies busy and lets
+    /// [`SignalingServerBuilder::idle_timeout`] notice peers whose connection silently died.
+    ///
+    /// Pings are disabled by default.
+    pub fn ping_interval(mut self, interval: Duration) -> Self {
+        self.heartbeat.ping_interval = Some(interval);
+        self
+    }
+
+    /// Drop peers that send nothing, not even a pong or [`PeerRequest::KeepAlive`], for the given
+    /// duration. They are removed from their room like any peer that disconnects.
+    ///
+    /// The timeout should be a few times longer than the ping interval set with
+    /// [`SignalingServerBuilder::ping_interval`]. There is no timeout by default.
+    ///
+    /// [`PeerRequest::KeepAlive`]: matchbox_protocol::PeerRequest::KeepAlive
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.heartbeat.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Limit how many connections may be open from the same IP address.
+    ///
+    /// Further connections from the address are closed right after the websocket upgrade with
+    /// [`LimitExceeded::ConnectionsPerIp`](crate::LimitExceeded::ConnectionsPerIp).
+    pub fn max_connections_per_ip(mut self, max: usize) -> Self {
+        self.limits.max_connections_per_ip = Some(max);
+        self
+    }
+
+    /// Limit how many connections may be open in total.
+    ///
+    /// Further connections are closed right after the websocket upgrade with
+    /// [`LimitExceeded::Connections`](crate::LimitExceeded::Connections).
+    pub fn max_connections(mut self, max: usize) -> Self {
+        self.limits.max_connections = Some(max);
+        self
+    }
+
+    /// Limit how many signaling messages a peer may send per second, allowing bursts of up to the
+    /// same number of messages.
+    ///
+    /// Peers sending more are disconnected with
+    /// [`LimitExceeded::MessageRate`](crate::LimitExceeded::MessageRate).
+    pub fn max_messages_per_second(mut self, max: u32) -> Self {
+        self.limits.max_messages_per_second = Some(max);
+        self
+    }
+
+    /// Limit the size of signaling messages in bytes.
+    ///
+    /// Peers sending larger messages are disconnected with
+    /// [`LimitExceeded::MessageSize`](crate::LimitExceeded::MessageSize). Such messages are
+    /// rejected while they are being received, so they are never buffered in full.
+    pub fn max_message_size(mut self, max: usize) -> Self {
+        self.limits.max_message_size = Some(max);
+        self
+    }
+
+    /// Set a callback triggered when a client breaks one of the limits, right before its
+    /// connection is closed.
+    pub fn on_limit_exceeded<F>(mut self, callback: F) -> Self
+    where
+        F: FnMut(LimitViolation) + Send + Sync + 'static,
+    {
+        self.shared_callbacks.on_limit_exceeded = Callback::from(callback);
+        self
+    }
+
     /// Apply permissive CORS middleware for debug purposes.
     pub fn cors(mut self) -> Self {
         self.router = self.router.layer(
@@ -122,22 +264,51 @@ where
         // Insert topology
         let state_machine: SignalingStateMachine<Cb, S> =
             SignalingStateMachine::from_topology(self.topology);
+        let (shutdown, shutdown_signal, connections_closed) = shutdown_channel();
         self.router = self
             .router
             .route("/", get(ws_handler::<Cb, S>))
             .route("/:path", get(ws_handler::<Cb, S>))
             .layer(Extension(state_machine))
+            .layer(Extension(SessionRegistry::new(
+                self.session_resume_grace_period,
+            )))
+            .layer(Extension(shutdown_signal))
+            .layer(Extension(self.heartbeat))
+            .layer(Extension(self.limits))
+            .layer(Extension(ConnectionCounter::new(self.limits)))
             .layer(Extension(self.shared_callbacks))
             .layer(Extension(self.callbacks))
             .layer(Extension(self.state));
-        let server = axum::Server::bind(&self.socket_addr).serve(
-            self.router
-                .into_make_service_with_connect_info::<SocketAddr>(),
-        );
+        let make_service = self
+            .router
+            .into_make_service_with_connect_info::<SocketAddr>();
+        #[cfg(feature = "tls")]
+        if let Some(tls) = self.tls {
+            let listener = std::net::TcpListener::bind(self.socket_addr)
+                .and_then(|listener| listener.set_nonblocking(true).map(|()| listener))
+                .unwrap_or_else(|e| panic!("error binding to {}: {e}", self.socket_addr));
+            let socket_addr = listener
+                .local_addr()
+                .expect("bound listener has an address");
+            return SignalingServer {
+                server: Listener::Https {
+                    listener,
+                    make_service,
+                    tls,
+                },
+                socket_addr,
+                shutdown,
+                connections_closed,
+            };
+        }
+        let server = axum::Server::bind(&self.socket_addr).serve(make_service);
         let socket_addr = server.local_addr();
         SignalingServer {
-            server,
+            server: Listener::Http(server),
             socket_addr,
+            shutdown,
+            connections_closed,
         }
     }
 }
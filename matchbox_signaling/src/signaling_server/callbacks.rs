@@ -1,5 +1,9 @@
-use crate::signaling_server::handlers::WsUpgradeMeta;
+use crate::signaling_server::{handlers::WsUpgradeMeta, limits::LimitViolation};
 use axum::response::Response;
+use futures::{
+    future::{self, BoxFuture},
+    Future,
+};
 use matchbox_protocol::PeerId;
 use std::{
     fmt,
@@ -9,18 +13,55 @@ use std::{
 
 /// Universal callback wrapper.
 ///
-/// An `Arc` wrapper is used to make it cloneable and thread safe.
+/// Wraps a synchronous or an asynchronous function. An `Arc` wrapper is used to make it cloneable
+/// and thread safe. Synchronous functions may be `FnMut`, so each one sits behind its own mutex,
+/// while asynchronous functions are called without a lock, so a slow one only delays the
+/// connection it is called for.
 pub struct Callback<In, Out = ()> {
     /// A callback which can be called multiple times
-    pub(crate) cb: Arc<Mutex<dyn FnMut(In) -> Out + Send + Sync>>,
+    pub(crate) cb: Arc<dyn Fn(In) -> BoxFuture<'static, Out> + Send + Sync>,
 }
 
-impl<In, Out, F: FnMut(In) -> Out + Send + Sync + 'static> From<F> for Callback<In, Out> {
+impl<In, Out, F> From<F> for Callback<In, Out>
+where
+    F: FnMut(In) -> Out + Send + Sync + 'static,
+    Out: Send + 'static,
+{
     fn from(func: F) -> Self {
+        let func = Mutex::new(func);
+        Callback {
+            cb: Arc::new(move |value| {
+                let out = {
+                    let mut lock = func.lock().expect("lock");
+                    (*lock)(value)
+                };
+                Box::pin(future::ready(out))
+            }),
+        }
+    }
+}
+
+impl<In, Out> Callback<In, Out> {
+    /// Creates a callback from an asynchronous function, which is awaited where the callback is
+    /// triggered. Calls are not serialized, so the function may run concurrently.
+    pub fn from_async<F, Fut>(func: F) -> Self
+    where
+        F: Fn(In) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Out> + Send + 'static,
+    {
         Callback {
-            cb: Arc::new(Mutex::new(func)),
+            cb: Arc::new(move |value| Box::pin(func(value))),
         }
     }
+
+    /// This method calls the callback's function, returning a future that resolves to its output.
+    ///
+    /// A synchronous function runs right away, but an asynchronous one only runs once the future
+    /// is polled.
+    #[must_use = "asynchronous callbacks do nothing unless the returned future is awaited"]
+    pub fn emit(&self, value: In) -> BoxFuture<'static, Out> {
+        (self.cb)(value)
+    }
 }
 
 impl<In, Out> Clone for Callback<In, Out> {
@@ -37,14 +78,6 @@ impl<In, Out> fmt::Debug for Callback<In, Out> {
     }
 }
 
-impl<In, Out> Callback<In, Out> {
-    /// This method calls the callback's function.
-    pub fn emit(&self, value: In) -> Out {
-        let mut lock = self.cb.lock().expect("lock");
-        (*lock)(value)
-    }
-}
-
 impl<In> Callback<In> {
     /// Creates a "no-op" callback which can be used when it is not suitable to use an
     /// `Option<Callback>`.
@@ -67,6 +100,9 @@ pub struct SharedCallbacks {
 
     /// Triggered on ID assignment for a socket.
     pub(crate) on_id_assignment: Callback<(SocketAddr, PeerId)>,
+
+    /// Triggered when a client breaks one of the limits.
+    pub(crate) on_limit_exceeded: Callback<LimitViolation>,
 }
 
 impl Default for SharedCallbacks {
@@ -74,6 +110,7 @@ impl Default for SharedCallbacks {
         Self {
             on_connection_request: Callback::from(|_| Ok(true)),
             on_id_assignment: Callback::default(),
+            on_limit_exceeded: Callback::default(),
         }
     }
 }
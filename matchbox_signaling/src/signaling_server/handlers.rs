@@ -1,24 +1,36 @@
 use crate::{
-    signaling_server::{callbacks::SharedCallbacks, SignalingState},
+    signaling_server::{
+        callbacks::SharedCallbacks,
+        heartbeat::{tick, Heartbeat},
+        limits::{ConnectionCounter, LimitViolation, Limits, MessageLimiter},
+        receiver::{MessageLimits, PeerReceiver},
+        session::{PeerSession, SessionRegistry},
+        shutdown::ShutdownSignal,
+        SignalingState,
+    },
     topologies::{
-        common_logic::{spawn_sender_task, try_send, SignalingChannel},
+        common_logic::{try_send, SignalingChannel},
         SignalingStateMachine,
     },
     SignalingCallbacks,
 };
 use axum::{
     extract::{
-        ws::{Message, WebSocket},
+        ws::{close_code, CloseFrame, Message},
         ConnectInfo, Path, Query, WebSocketUpgrade,
     },
     response::IntoResponse,
     Extension,
 };
-use futures::{stream::SplitStream, StreamExt};
+use futures::{future, SinkExt, StreamExt};
 use hyper::{HeaderMap, StatusCode};
-use matchbox_protocol::{JsonSignalEvent, PeerEvent, PeerId};
+use matchbox_protocol::{
+    JsonSignalEvent, PeerEvent, PeerId, ResumeToken, RESUMABLE_QUERY_PARAM,
+    RESUME_TOKEN_QUERY_PARAM,
+};
 use std::{collections::HashMap, net::SocketAddr};
-use tracing::{error, info};
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
 
 /// Metastate used during by a signaling server's runtime
 pub struct WsStateMeta<Cb, S> {
@@ -27,7 +39,9 @@ pub struct WsStateMeta<Cb, S> {
     /// The channel to signal this peer through
     pub sender: SignalingChannel,
     /// The receiver to receive from this peer through
-    pub receiver: SplitStream<WebSocket>,
+    pub receiver: PeerReceiver,
+    /// The session of this peer, which may be resumed after the websocket drops
+    pub session: PeerSession,
     /// Callbacks associated with the topology
     pub callbacks: Cb,
     /// State associated with the topology
@@ -35,11 +49,21 @@ pub struct WsStateMeta<Cb, S> {
 }
 
 /// Metadata captured at the time of websocket upgrade
+///
+/// Passed to [`SignalingServerBuilder::on_connection_request`], e.g. to check credentials sent
+/// by the client in a header or, for browsers that can't set headers, the
+/// [`AUTH_TOKEN_QUERY_PARAM`](matchbox_protocol::AUTH_TOKEN_QUERY_PARAM) query parameter.
+///
+/// [`SignalingServerBuilder::on_connection_request`]: crate::SignalingServerBuilder::on_connection_request
 #[derive(Debug, Clone)]
 pub struct WsUpgradeMeta {
+    /// The address the client connected from
     pub origin: SocketAddr,
+    /// The path the client connected to, usually the room id
     pub path: Option<String>,
+    /// The query parameters of the request
     pub query_params: HashMap<String, String>,
+    /// The headers of the request, e.g. `Authorization`
     pub headers: HeaderMap,
 }
 
@@ -55,6 +79,11 @@ pub(crate) async fn ws_handler<Cb, S>(
     Extension(callbacks): Extension<Cb>,
     Extension(state): Extension<S>,
     Extension(state_machine): Extension<SignalingStateMachine<Cb, S>>,
+    Extension(sessions): Extension<SessionRegistry>,
+    Extension(shutdown): Extension<ShutdownSignal>,
+    Extension(heartbeat): Extension<Heartbeat>,
+    Extension(limits): Extension<Limits>,
+    Extension(connections): Extension<ConnectionCounter>,
     ConnectInfo(origin): ConnectInfo<SocketAddr>,
 ) -> impl IntoResponse
 where
@@ -63,48 +92,167 @@ where
 {
     info!("`{origin}` connected.");
 
+    let permit = match connections.acquire(origin.ip()) {
+        Ok(permit) => permit,
+        Err(limit) => {
+            warn!("closing connection from `{origin}`: {limit}");
+            shared_callbacks
+                .on_limit_exceeded
+                .emit(LimitViolation {
+                    origin,
+                    peer_id: None,
+                    limit,
+                })
+                .await;
+            // Upgrade anyway, so the client learns why through the close frame
+            return ws.on_upgrade(move |mut ws| async move {
+                if let Err(e) = ws.send(limit.close_frame()).await {
+                    warn!("error closing connection from `{origin}`: {e:?}");
+                }
+            });
+        }
+    };
+
     let path = path.map(|path| path.0);
+    let resume_token = query_params
+        .get(RESUME_TOKEN_QUERY_PARAM)
+        .and_then(|token| token.parse::<uuid::Uuid>().ok())
+        .map(ResumeToken);
+    // Older clients would choke on the resume token event, so only send it to those asking for it
+    let send_token = sessions.is_enabled()
+        && (resume_token.is_some() || query_params.contains_key(RESUMABLE_QUERY_PARAM));
     let meta = WsUpgradeMeta {
         origin,
-        path,
+        path: path.clone(),
         query_params,
         headers,
     };
 
     // Lifecycle event: On Connection Request
-    match shared_callbacks.on_connection_request.emit(meta) {
+    match shared_callbacks.on_connection_request.emit(meta).await {
         Ok(true) => {}
         Ok(false) => return (StatusCode::UNAUTHORIZED).into_response(),
         Err(e) => return e,
     };
 
-    // Finalize the upgrade process by returning upgrade callback to client
-    // Generate an ID for the peer
-    let peer_id = uuid::Uuid::new_v4().into();
+    // Refuse to buffer messages above the size limit, instead of checking them once received
+    let ws = match limits.max_message_size {
+        Some(max) => ws.max_message_size(max).max_frame_size(max),
+        None => ws,
+    };
 
-    // Lifecycle event: On ID Assignment
-    shared_callbacks.on_id_assignment.emit((origin, peer_id));
+    // Keep the peer's ID if it can resume its previous session, otherwise generate one
+    let resumable = resume_token.and_then(|token| Some((token, sessions.peer_id(token, &path)?)));
+    let peer_id = match resumable {
+        Some((_, peer_id)) => peer_id,
+        None => uuid::Uuid::new_v4().into(),
+    };
 
-    ws.on_upgrade(move |ws| {
-        let (ws_sink, receiver) = ws.split();
-        let sender = spawn_sender_task(ws_sink);
+    // Lifecycle event: On ID Assignment
+    shared_callbacks
+        .on_id_assignment
+        .emit((origin, peer_id))
+        .await;
 
-        // Send ID to peer
-        let event_text = JsonSignalEvent::Peer(PeerEvent::IdAssigned(peer_id)).to_string();
-        let event = Message::Text(event_text.clone());
-        if let Err(e) = try_send(&sender, event) {
-            error!("error sending to {peer_id}: {e:?}");
-        } else {
-            info!("{peer_id} -> {event_text}");
+    // Finalize the upgrade process by returning upgrade callback to client
+    ws.on_upgrade(move |mut ws| async move {
+        // Counts the connection until it is closed
+        let _permit = permit;
+        // Only take the session over once the new websocket exists, so a failed upgrade leaves it
+        // to the previous connection to hold or end the session
+        let session = match resumable {
+            Some((token, _)) => match sessions.resume(token, &path) {
+                Some((_, session)) => {
+                    info!("`{origin}` resumed session of {peer_id}");
+                    session
+                }
+                None => {
+                    // The session expired during the upgrade, and the peer may still be on its
+                    // way out, so its ID can't be handed out again. The client can reconnect.
+                    warn!("closing connection from `{origin}`: session of {peer_id} expired");
+                    let close = Message::Close(Some(CloseFrame {
+                        code: close_code::AGAIN,
+                        reason: "session expired".into(),
+                    }));
+                    if let Err(e) = ws.send(close).await {
+                        warn!("error closing connection from `{origin}`: {e:?}");
+                    }
+                    return;
+                }
+            },
+            None => sessions.create(peer_id, path),
         };
+        let (mut ws_sink, receiver) = ws.split();
+        let (sender, mut outgoing) = mpsc::unbounded_channel();
 
+        // Send ID to peer, followed by the token to resume its session with
+        let mut events = vec![PeerEvent::IdAssigned(peer_id)];
+        if send_token {
+            events.push(PeerEvent::ResumeToken(session.token()));
+        }
+        for event in events {
+            let event_text = JsonSignalEvent::Peer(event).to_string();
+            let event = Message::Text(event_text.clone());
+            if let Err(e) = try_send(&sender, event) {
+                error!("error sending to {peer_id}: {e:?}");
+            } else {
+                info!("{peer_id} -> {event_text}");
+            };
+        }
+
+        let limits = MessageLimits {
+            origin,
+            limiter: MessageLimiter::new(&limits),
+            sender: sender.clone(),
+            on_limit_exceeded: shared_callbacks.on_limit_exceeded,
+        };
+        let closer = sender.clone();
+        let receiver = PeerReceiver::new(
+            peer_id,
+            receiver,
+            heartbeat.idle_timeout,
+            limits,
+            shutdown.clone(),
+        );
         let meta = WsStateMeta {
             peer_id,
             sender,
             receiver,
+            session,
             callbacks,
             state,
         };
-        (*state_machine.0)(meta)
+        let forward = async {
+            let mut pings = heartbeat.pings();
+            loop {
+                let message = tokio::select! {
+                    message = outgoing.recv() => match message {
+                        Some(Ok(message)) => message,
+                        _ => break,
+                    },
+                    _ = tick(&mut pings) => Message::Ping(Vec::new()),
+                };
+                let close = matches!(message, Message::Close(_));
+                if ws_sink.send(message).await.is_err() || close {
+                    break;
+                }
+            }
+        };
+        // On shutdown, the peer's messages end and the topology removes the peer as usual
+        let state_machine = async {
+            (*state_machine.0)(meta).await;
+            if shutdown.is_triggered() {
+                // Let the peer know why the connection is closing, so it can reconnect elsewhere
+                let close = Message::Close(Some(CloseFrame {
+                    code: close_code::AWAY,
+                    reason: "server shutting down".into(),
+                }));
+                if let Err(e) = try_send(&closer, close) {
+                    error!("error closing connection to {peer_id}: {e:?}");
+                }
+            }
+            drop(closer);
+        };
+        future::join(forward, state_machine).await;
     })
 }
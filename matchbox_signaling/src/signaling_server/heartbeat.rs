@@ -0,0 +1,29 @@
+use std::time::Duration;
+use tokio::time::{Instant, Interval};
+
+/// How the server checks that peers are still there
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct Heartbeat {
+    /// How often to ping peers
+    pub(crate) ping_interval: Option<Duration>,
+    /// How long a peer may stay silent before its connection is dropped
+    pub(crate) idle_timeout: Option<Duration>,
+}
+
+impl Heartbeat {
+    /// An interval ticking whenever a ping is due, if pings are enabled
+    pub(crate) fn pings(&self) -> Option<Interval> {
+        self.ping_interval
+            .map(|period| tokio::time::interval_at(Instant::now() + period, period))
+    }
+}
+
+/// Resolves on the next tick of the interval, or never if there is none
+pub(crate) async fn tick(interval: &mut Option<Interval>) {
+    match interval {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
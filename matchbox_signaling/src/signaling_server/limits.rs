@@ -0,0 +1,181 @@
+use axum::extract::ws::{close_code, CloseFrame, Message};
+use matchbox_protocol::PeerId;
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+};
+use tokio::time::Instant;
+
+/// Limits protecting the server from misbehaving clients, all disabled by default
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct Limits {
+    pub(crate) max_connections_per_ip: Option<usize>,
+    pub(crate) max_connections: Option<usize>,
+    pub(crate) max_messages_per_second: Option<u32>,
+    pub(crate) max_message_size: Option<usize>,
+}
+
+/// A limit set on the [`SignalingServerBuilder`] that a client broke.
+///
+/// The connection is closed with [`LimitExceeded::close_code`], and the description as reason.
+///
+/// [`SignalingServerBuilder`]: crate::SignalingServerBuilder
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, thiserror::Error)]
+pub enum LimitExceeded {
+    /// Too many connections from the same IP address
+    #[error("too many connections from this address")]
+    ConnectionsPerIp,
+
+    /// Too many connections to the server
+    #[error("too many connections")]
+    Connections,
+
+    /// Too many signaling messages per second from a peer
+    #[error("too many messages")]
+    MessageRate,
+
+    /// A signaling message larger than the maximum size
+    #[error("message too big")]
+    MessageSize,
+}
+
+impl LimitExceeded {
+    /// The websocket close code the connection is closed with.
+    ///
+    /// Connection limits close with 1013 (try again later), the message rate limit with 1008
+    /// (policy violation) and the message size limit with 1009 (message too big).
+    pub fn close_code(&self) -> u16 {
+        match self {
+            LimitExceeded::ConnectionsPerIp | LimitExceeded::Connections => close_code::AGAIN,
+            LimitExceeded::MessageRate => close_code::POLICY,
+            LimitExceeded::MessageSize => close_code::SIZE,
+        }
+    }
+
+    pub(crate) fn close_frame(&self) -> Message {
+        Message::Close(Some(CloseFrame {
+            code: self.close_code(),
+            reason: self.to_string().into(),
+        }))
+    }
+}
+
+/// A client breaking one of the limits, passed to
+/// [`SignalingServerBuilder::on_limit_exceeded`].
+///
+/// [`SignalingServerBuilder::on_limit_exceeded`]: crate::SignalingServerBuilder::on_limit_exceeded
+#[derive(Debug, Clone)]
+pub struct LimitViolation {
+    /// The address the client connected from
+    pub origin: SocketAddr,
+    /// The peer, if it was assigned an ID, which is not the case for connection limits
+    pub peer_id: Option<PeerId>,
+    /// The limit that was broken
+    pub limit: LimitExceeded,
+}
+
+#[derive(Debug, Default)]
+struct ConnectionCount {
+    total: usize,
+    per_ip: HashMap<IpAddr, usize>,
+}
+
+/// Counts open connections, in total and per IP address
+#[derive(Debug, Clone)]
+pub(crate) struct ConnectionCounter {
+    limits: Limits,
+    count: Arc<Mutex<ConnectionCount>>,
+}
+
+impl ConnectionCounter {
+    pub(crate) fn new(limits: Limits) -> Self {
+        Self {
+            limits,
+            count: Default::default(),
+        }
+    }
+
+    /// Counts a new connection from the given address, unless it would break a limit
+    pub(crate) fn acquire(&self, ip: IpAddr) -> Result<ConnectionPermit, LimitExceeded> {
+        let mut count = self.count.lock().expect("lock");
+        if matches!(self.limits.max_connections, Some(max) if count.total >= max) {
+            return Err(LimitExceeded::Connections);
+        }
+        let from_ip = count.per_ip.get(&ip).copied().unwrap_or_default();
+        if matches!(self.limits.max_connections_per_ip, Some(max) if from_ip >= max) {
+            return Err(LimitExceeded::ConnectionsPerIp);
+        }
+        count.total += 1;
+        *count.per_ip.entry(ip).or_default() += 1;
+        Ok(ConnectionPermit {
+            ip,
+            count: self.count.clone(),
+        })
+    }
+}
+
+/// A counted connection, which stops being counted when dropped
+#[derive(Debug)]
+pub(crate) struct ConnectionPermit {
+    ip: IpAddr,
+    count: Arc<Mutex<ConnectionCount>>,
+}
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        let mut count = self.count.lock().expect("lock");
+        count.total -= 1;
+        if let Some(from_ip) = count.per_ip.get_mut(&self.ip) {
+            *from_ip -= 1;
+            if *from_ip == 0 {
+                count.per_ip.remove(&self.ip);
+            }
+        }
+    }
+}
+
+/// Enforces the message limits for a single peer
+#[derive(Debug)]
+pub(crate) struct MessageLimiter {
+    max_rate: Option<u32>,
+    max_size: Option<usize>,
+    /// Messages the peer may still send right away, refilled at the maximum rate
+    budget: f64,
+    refilled_at: Instant,
+}
+
+impl MessageLimiter {
+    pub(crate) fn new(limits: &Limits) -> Self {
+        Self {
+            max_rate: limits.max_messages_per_second,
+            max_size: limits.max_message_size,
+            budget: limits.max_messages_per_second.unwrap_or_default() as f64,
+            refilled_at: Instant::now(),
+        }
+    }
+
+    /// Checks a message received from the peer against the limits
+    pub(crate) fn check(&mut self, message: &Message) -> Result<(), LimitExceeded> {
+        let size = match message {
+            Message::Text(text) => text.len(),
+            Message::Binary(data) => data.len(),
+            // Control messages are not signaling messages
+            _ => return Ok(()),
+        };
+        if matches!(self.max_size, Some(max) if size > max) {
+            return Err(LimitExceeded::MessageSize);
+        }
+        if let Some(rate) = self.max_rate {
+            let now = Instant::now();
+            let elapsed = now.duration_since(self.refilled_at).as_secs_f64();
+            self.budget = (self.budget + elapsed * rate as f64).min(rate as f64);
+            self.refilled_at = now;
+            if self.budget < 1.0 {
+                return Err(LimitExceeded::MessageRate);
+            }
+            self.budget -= 1.0;
+        }
+        Ok(())
+    }
+}
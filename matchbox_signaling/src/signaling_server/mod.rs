@@ -2,7 +2,14 @@ pub(crate) mod builder;
 pub(crate) mod callbacks;
 pub(crate) mod error;
 pub(crate) mod handlers;
+pub(crate) mod heartbeat;
+pub(crate) mod limits;
+pub(crate) mod receiver;
 pub(crate) mod server;
+pub(crate) mod session;
+pub(crate) mod shutdown;
+#[cfg(feature = "tls")]
+pub(crate) mod tls;
 
 pub use server::SignalingServer;
 
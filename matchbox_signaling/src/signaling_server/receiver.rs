@@ -0,0 +1,176 @@
+use crate::{
+    signaling_server::{
+        limits::{LimitExceeded, LimitViolation, MessageLimiter},
+        shutdown::ShutdownSignal,
+    },
+    topologies::common_logic::{try_send, SignalingChannel},
+    Callback,
+};
+use axum::extract::ws::{Message, WebSocket};
+use futures::{stream::SplitStream, Future, Stream, StreamExt};
+use matchbox_protocol::PeerId;
+use std::{
+    error::Error,
+    net::SocketAddr,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::time::{Instant, Sleep};
+use tracing::{info, warn};
+
+/// Enforces the message limits of a peer, closing its connection when it breaks one
+pub(crate) struct MessageLimits {
+    pub(crate) origin: SocketAddr,
+    pub(crate) limiter: MessageLimiter,
+    pub(crate) sender: SignalingChannel,
+    pub(crate) on_limit_exceeded: Callback<LimitViolation>,
+}
+
+/// The stream of messages sent by a peer.
+///
+/// Ends when the websocket closes, when the peer sends nothing, not even a pong, within the idle
+/// timeout set with [`SignalingServerBuilder::idle_timeout`], when the peer breaks one of the
+/// message limits set on the builder, or when the server shuts down. Topologies then remove the
+/// peer as if it disconnected.
+///
+/// [`SignalingServerBuilder::idle_timeout`]: crate::SignalingServerBuilder::idle_timeout
+pub struct PeerReceiver {
+    peer_id: PeerId,
+    inner: SplitStream<WebSocket>,
+    idle_timeout: Option<Duration>,
+    deadline: Option<Pin<Box<Sleep>>>,
+    limits: MessageLimits,
+    shutdown: Pin<Box<dyn Future<Output = ()> + Send>>,
+    timed_out: bool,
+    shut_down: bool,
+    limit_exceeded: bool,
+    closed: bool,
+}
+
+impl PeerReceiver {
+    pub(crate) fn new(
+        peer_id: PeerId,
+        inner: SplitStream<WebSocket>,
+        idle_timeout: Option<Duration>,
+        limits: MessageLimits,
+        mut shutdown: ShutdownSignal,
+    ) -> Self {
+        Self {
+            peer_id,
+            inner,
+            idle_timeout,
+            deadline: idle_timeout.map(|timeout| Box::pin(tokio::time::sleep(timeout))),
+            limits,
+            shutdown: Box::pin(async move { shutdown.triggered().await }),
+            timed_out: false,
+            shut_down: false,
+            limit_exceeded: false,
+            closed: false,
+        }
+    }
+
+    /// Whether the stream ended because the peer went quiet
+    pub fn timed_out(&self) -> bool {
+        self.timed_out
+    }
+
+    /// Whether the peer may resume its session now that the stream ended, see
+    /// [`PeerSession::hold`](crate::PeerSession::hold).
+    ///
+    /// Connections closed by the server because it is shutting down or because the peer broke a
+    /// limit can't be resumed.
+    pub fn may_resume(&self) -> bool {
+        !self.shut_down && !self.limit_exceeded
+    }
+
+    fn activity(&mut self) {
+        if let (Some(deadline), Some(timeout)) = (&mut self.deadline, self.idle_timeout) {
+            deadline.as_mut().reset(Instant::now() + timeout);
+        }
+    }
+
+    /// Whether the message is within the limits, otherwise closes the connection
+    fn within_limits(&mut self, message: &Message) -> bool {
+        let Err(limit) = self.limits.limiter.check(message) else {
+            return true;
+        };
+        self.exceeded(limit);
+        false
+    }
+
+    /// Closes the connection of a peer that broke a limit
+    fn exceeded(&mut self, limit: LimitExceeded) {
+        warn!("closing connection to {}: {limit}", self.peer_id);
+        self.limit_exceeded = true;
+        self.closed = true;
+        if let Err(e) = try_send(&self.limits.sender, limit.close_frame()) {
+            warn!("error closing connection to {}: {e:?}", self.peer_id);
+        }
+        // Messages are received synchronously, so the callback completes on its own
+        tokio::spawn(self.limits.on_limit_exceeded.emit(LimitViolation {
+            origin: self.limits.origin,
+            peer_id: Some(self.peer_id),
+            limit,
+        }));
+    }
+}
+
+impl Stream for PeerReceiver {
+    type Item = Result<Message, axum::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.closed {
+            return Poll::Ready(None);
+        }
+        if self.shutdown.as_mut().poll(cx).is_ready() {
+            info!(
+                "closing connection to {}, the server is shutting down",
+                self.peer_id
+            );
+            self.shut_down = true;
+            self.closed = true;
+            return Poll::Ready(None);
+        }
+        loop {
+            match self.inner.poll_next_unpin(cx) {
+                // Answers to our pings only keep the connection alive
+                Poll::Ready(Some(Ok(Message::Pong(_)))) => self.activity(),
+                Poll::Ready(Some(Ok(message))) => {
+                    self.activity();
+                    if !self.within_limits(&message) {
+                        return Poll::Ready(None);
+                    }
+                    return Poll::Ready(Some(Ok(message)));
+                }
+                // The websocket stops reading messages above the size limit before they are
+                // buffered in full, see `ws_handler`
+                Poll::Ready(Some(Err(e))) if is_capacity_error(&e) => {
+                    self.exceeded(LimitExceeded::MessageSize);
+                    return Poll::Ready(None);
+                }
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => break,
+            }
+        }
+        let expired = match &mut self.deadline {
+            Some(deadline) => deadline.as_mut().poll(cx).is_ready(),
+            None => false,
+        };
+        if expired {
+            info!("{} timed out", self.peer_id);
+            self.timed_out = true;
+            self.closed = true;
+            return Poll::Ready(None);
+        }
+        Poll::Pending
+    }
+}
+
+fn is_capacity_error(error: &axum::Error) -> bool {
+    matches!(
+        error.source().and_then(|e| e.downcast_ref()),
+        Some(tungstenite::Error::Capacity(_))
+    )
+}
@@ -1,5 +1,5 @@
 use crate::{
-    signaling_server::builder::SignalingServerBuilder,
+    signaling_server::{builder::SignalingServerBuilder, shutdown::ShutdownHandle},
     topologies::{
         client_server::{ClientServer, ClientServerCallbacks, ClientServerState},
         full_mesh::{FullMesh, FullMeshCallbacks, FullMeshState},
@@ -8,6 +8,26 @@ use crate::{
 use axum::{extract::connect_info::IntoMakeServiceWithConnectInfo, Router, Server};
 use hyper::server::conn::AddrIncoming;
 use std::net::SocketAddr;
+use tokio::sync::mpsc;
+
+#[cfg(feature = "tls")]
+use crate::signaling_server::tls::TlsConfig;
+
+pub(crate) type MakeService = IntoMakeServiceWithConnectInfo<Router, SocketAddr>;
+
+/// The low-level server, serving plain HTTP or terminating TLS
+#[derive(Debug)]
+pub(crate) enum Listener {
+    /// The axum server
+    Http(Server<AddrIncoming, MakeService>),
+    /// A listener and service to serve with TLS
+    #[cfg(feature = "tls")]
+    Https {
+        listener: std::net::TcpListener,
+        make_service: MakeService,
+        tls: TlsConfig,
+    },
+}
 
 /// Contains the interface end of a signaling server
 #[derive(Debug)]
@@ -15,8 +35,14 @@ pub struct SignalingServer {
     /// The socket address bound for this server
     pub(crate) socket_addr: SocketAddr,
 
-    /// The low-level axum server
-    pub(crate) server: Server<AddrIncoming, IntoMakeServiceWithConnectInfo<Router, SocketAddr>>,
+    /// The low-level server
+    pub(crate) server: Listener,
+
+    /// Triggers a graceful shutdown of this server
+    pub(crate) shutdown: ShutdownHandle,
+
+    /// Yields `None` once every websocket connection is closed
+    pub(crate) connections_closed: mpsc::Receiver<()>,
 }
 
 /// Common methods
@@ -40,12 +66,63 @@ impl SignalingServer {
         self.socket_addr
     }
 
+    /// Returns a handle to gracefully shut down this server, see [`ShutdownHandle`]
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
     /// Serve the signaling server
+    ///
+    /// Runs until the server fails, or until a shutdown is triggered through a
+    /// [`ShutdownHandle`] and every websocket connection is closed.
     pub async fn serve(self) -> Result<(), crate::Error> {
-        // TODO: Shouldn't this return Result<!, crate::Error>?
-        match self.server.await {
-            Ok(()) => Ok(()),
-            Err(e) => Err(crate::Error::from(e)),
+        let SignalingServer {
+            server,
+            shutdown,
+            mut connections_closed,
+            ..
+        } = self;
+        let mut triggered = shutdown.subscribe();
+        let shutdown_triggered = async move {
+            // `shutdown` outlives this future, so the sender is never dropped
+            let _ = triggered.wait_for(|&shutdown| shutdown).await;
+        };
+        match server {
+            Listener::Http(server) => {
+                if let Err(e) = server.with_graceful_shutdown(shutdown_triggered).await {
+                    return Err(crate::Error::from(e));
+                }
+            }
+            #[cfg(feature = "tls")]
+            Listener::Https {
+                listener,
+                make_service,
+                tls,
+            } => {
+                let handle = axum_server::Handle::new();
+                let serve = axum_server::from_tcp_rustls(listener, tls.config.clone())
+                    .handle(handle.clone())
+                    .serve(make_service);
+                let stop = async move {
+                    shutdown_triggered.await;
+                    handle.graceful_shutdown(None);
+                    std::future::pending::<()>().await
+                };
+                // Stops the server on shutdown and keeps its certificate up to date
+                let background = futures::future::join(stop, tls.watch());
+                tokio::select! {
+                    result = serve => {
+                        if let Err(e) = result {
+                            return Err(crate::Error::Tls(e.to_string()));
+                        }
+                    }
+                    _ = background => unreachable!("runs until the server returns"),
+                }
+            }
         }
+
+        // No new connections are accepted, wait for the open websockets to be closed
+        let _ = connections_closed.recv().await;
+        Ok(())
     }
 }
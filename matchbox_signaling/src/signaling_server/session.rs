@@ -0,0 +1,191 @@
+use crate::topologies::common_logic::StateObj;
+use matchbox_protocol::{PeerId, ResumeToken};
+use std::{collections::HashMap, time::Duration};
+use tokio::sync::oneshot;
+
+/// A session which a peer can resume by presenting its [`ResumeToken`]
+#[derive(Debug)]
+struct SessionEntry {
+    peer_id: PeerId,
+    /// The path the peer connected to, so the session can't be resumed in another room
+    path: Option<String>,
+    /// Notifies the connection currently owning the session that it was taken over
+    takeover_tx: oneshot::Sender<()>,
+}
+
+/// Keeps track of resumable sessions, keyed by their resume token
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SessionRegistry {
+    /// How long a dropped peer is held before it is removed, `None` disables resuming
+    grace_period: Option<Duration>,
+    sessions: StateObj<HashMap<ResumeToken, SessionEntry>>,
+}
+
+impl SessionRegistry {
+    pub(crate) fn new(grace_period: Option<Duration>) -> Self {
+        Self {
+            grace_period,
+            sessions: Default::default(),
+        }
+    }
+
+    /// Whether sessions can be resumed at all
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.grace_period.is_some()
+    }
+
+    /// Starts a new session for a peer connected to the given path
+    pub(crate) fn create(&self, peer_id: PeerId, path: Option<String>) -> PeerSession {
+        let token = ResumeToken(uuid::Uuid::new_v4());
+        let (takeover_tx, takeover_rx) = oneshot::channel();
+        self.sessions.lock().unwrap().insert(
+            token,
+            SessionEntry {
+                peer_id,
+                path,
+                takeover_tx,
+            },
+        );
+        PeerSession {
+            token,
+            resumed: false,
+            taken_over: false,
+            takeover_rx: Some(takeover_rx),
+            registry: self.clone(),
+        }
+    }
+
+    /// Looks up the peer of an existing session without taking it over, returning `None` if the
+    /// token is unknown, expired, was issued for another path, or resuming is disabled.
+    pub(crate) fn peer_id(&self, token: ResumeToken, path: &Option<String>) -> Option<PeerId> {
+        self.grace_period?;
+        let sessions = self.sessions.lock().unwrap();
+        let entry = sessions.get(&token).filter(|entry| entry.path == *path)?;
+        Some(entry.peer_id)
+    }
+
+    /// Takes over an existing session, returning `None` if the token is unknown, expired, was
+    /// issued for another path, or resuming is disabled.
+    pub(crate) fn resume(
+        &self,
+        token: ResumeToken,
+        path: &Option<String>,
+    ) -> Option<(PeerId, PeerSession)> {
+        self.grace_period?;
+        let mut sessions = self.sessions.lock().unwrap();
+        let entry = sessions
+            .get_mut(&token)
+            .filter(|entry| entry.path == *path)?;
+        let (takeover_tx, takeover_rx) = oneshot::channel();
+        // The previous connection may already be gone, in which case nobody needs to know.
+        _ = std::mem::replace(&mut entry.takeover_tx, takeover_tx).send(());
+        let session = PeerSession {
+            token,
+            resumed: true,
+            taken_over: false,
+            takeover_rx: Some(takeover_rx),
+            registry: self.clone(),
+        };
+        Some((entry.peer_id, session))
+    }
+}
+
+/// A peer's signaling session, which may outlive a single websocket connection.
+///
+/// If the server was built with
+/// [`SignalingServerBuilder::session_resume_grace_period`](crate::SignalingServerBuilder::session_resume_grace_period),
+/// a peer whose websocket drops can reconnect with its [`ResumeToken`] and keep its [`PeerId`].
+/// Topologies use [`PeerSession::superseded`] and [`PeerSession::hold`] to find out whether the
+/// peer needs to be removed. The session ends when this handle is dropped without having been
+/// taken over.
+#[derive(Debug)]
+pub struct PeerSession {
+    token: ResumeToken,
+    resumed: bool,
+    taken_over: bool,
+    takeover_rx: Option<oneshot::Receiver<()>>,
+    registry: SessionRegistry,
+}
+
+impl PeerSession {
+    /// The token the peer can present to resume this session
+    pub fn token(&self) -> ResumeToken {
+        self.token
+    }
+
+    /// Whether this connection resumed an earlier session, in which case other peers already know
+    /// about this peer and should not be told again.
+    pub fn is_resumed(&self) -> bool {
+        self.resumed
+    }
+
+    /// Completes when another connection resumes this session while this one is still open.
+    ///
+    /// The topology should then stop handling this connection, without removing the peer.
+    pub async fn superseded(&mut self) {
+        if let Some(takeover_rx) = self.takeover_rx.as_mut() {
+            let taken_over = takeover_rx.await.is_ok();
+            self.takeover_rx = None;
+            self.taken_over = taken_over;
+            if taken_over {
+                return;
+            }
+        }
+        // The session is gone, so it can never be taken over
+        std::future::pending::<()>().await
+    }
+
+    /// Holds the session after the peer's connection closed, waiting for the peer to resume it.
+    ///
+    /// Returns `true` if the session was resumed by a new connection, in which case the peer must
+    /// not be removed. Returns `false` once the grace period expired (or immediately if resuming
+    /// is disabled), in which case the peer should be removed as usual.
+    pub async fn hold(mut self) -> bool {
+        if self.taken_over {
+            return true;
+        }
+
+        if let (Some(grace_period), Some(takeover_rx)) =
+            (self.registry.grace_period, self.takeover_rx.as_mut())
+        {
+            let taken_over = tokio::select! {
+                taken_over = takeover_rx => Some(taken_over.is_ok()),
+                _ = tokio::time::sleep(grace_period) => None,
+            };
+            match taken_over {
+                Some(true) => {
+                    self.taken_over = true;
+                    return true;
+                }
+                Some(false) => self.takeover_rx = None,
+                None => {}
+            }
+        }
+
+        self.release()
+    }
+
+    /// Removes the session from the registry unless it was taken over, returning whether it was.
+    fn release(&mut self) -> bool {
+        if self.taken_over {
+            return true;
+        }
+        let mut sessions = self.registry.sessions.lock().unwrap();
+        // Resuming happens while holding the lock, so this can't race with a takeover
+        if let Some(takeover_rx) = self.takeover_rx.as_mut() {
+            if takeover_rx.try_recv().is_ok() {
+                self.taken_over = true;
+                return true;
+            }
+        }
+        sessions.remove(&self.token);
+        false
+    }
+}
+
+impl Drop for PeerSession {
+    fn drop(&mut self) {
+        // Topologies that don't hold sessions end them as soon as the connection is done
+        self.release();
+    }
+}
@@ -0,0 +1,66 @@
+use std::sync::Arc;
+use tokio::sync::{mpsc, watch};
+
+/// A handle to gracefully shut down a running [`SignalingServer`].
+///
+/// Triggering it stops the server from accepting new connections. Every connected peer is removed
+/// as if it disconnected, without holding its session, and its websocket is closed with a close
+/// frame with the [`close_code::AWAY`] code. [`SignalingServer::serve`] returns once all
+/// connections are closed.
+///
+/// [`SignalingServer`]: crate::SignalingServer
+/// [`SignalingServer::serve`]: crate::SignalingServer::serve
+/// [`close_code::AWAY`]: axum::extract::ws::close_code::AWAY
+#[derive(Debug, Clone)]
+pub struct ShutdownHandle(Arc<watch::Sender<bool>>);
+
+impl ShutdownHandle {
+    /// Shut down the server. Calling this more than once has no further effect.
+    pub fn shutdown(&self) {
+        self.0.send_replace(true);
+    }
+
+    /// Whether a shutdown was triggered
+    pub fn is_shutdown(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    pub(crate) fn subscribe(&self) -> watch::Receiver<bool> {
+        self.0.subscribe()
+    }
+}
+
+/// Lets a connection find out about a shutdown, while keeping the server from returning until
+/// the connection is dropped
+#[derive(Debug, Clone)]
+pub(crate) struct ShutdownSignal {
+    triggered: watch::Receiver<bool>,
+    _connection: mpsc::Sender<()>,
+}
+
+impl ShutdownSignal {
+    /// Whether a shutdown was triggered
+    pub(crate) fn is_triggered(&self) -> bool {
+        *self.triggered.borrow()
+    }
+
+    /// Resolves once a shutdown is triggered
+    pub(crate) async fn triggered(&mut self) {
+        if self.triggered.wait_for(|&shutdown| shutdown).await.is_err() {
+            // The server and all handles are gone, so there is no shutdown to wait for
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
+/// Creates a shutdown handle, the signal handed to connections, and a receiver which yields `None`
+/// once every clone of the signal is dropped
+pub(crate) fn shutdown_channel() -> (ShutdownHandle, ShutdownSignal, mpsc::Receiver<()>) {
+    let (handle, triggered) = watch::channel(false);
+    let (connection, connections_closed) = mpsc::channel(1);
+    let signal = ShutdownSignal {
+        triggered,
+        _connection: connection,
+    };
+    (ShutdownHandle(Arc::new(handle)), signal, connections_closed)
+}
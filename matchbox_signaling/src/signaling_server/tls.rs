@@ -0,0 +1,203 @@
+use axum_server::tls_rustls::RustlsConfig;
+use rustls::{Certificate, PrivateKey, ServerConfig, SignatureScheme};
+use rustls_pemfile::Item;
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use tracing::{info, warn};
+
+/// How often certificate files are checked for changes by default
+const DEFAULT_RELOAD_INTERVAL: Duration = Duration::from_secs(10);
+
+/// A certificate and private key for a [`SignalingServer`] to terminate TLS with, so clients can
+/// connect through `wss://`.
+///
+/// Pass it to [`SignalingServerBuilder::tls`].
+///
+/// [`SignalingServer`]: crate::SignalingServer
+/// [`SignalingServerBuilder::tls`]: crate::SignalingServerBuilder::tls
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    pub(crate) config: RustlsConfig,
+    pub(crate) files: Option<CertificateFiles>,
+}
+
+impl TlsConfig {
+    /// Creates a TLS configuration from a PEM encoded certificate chain and private key.
+    pub fn from_pem(cert: &[u8], key: &[u8]) -> Result<Self, crate::Error> {
+        let config = server_config(cert, key)?;
+        Ok(Self {
+            config: RustlsConfig::from_config(Arc::new(config)),
+            files: None,
+        })
+    }
+
+    /// Creates a TLS configuration from PEM encoded certificate chain and private key files.
+    ///
+    /// While the server is running, the files are checked for changes and the certificate is
+    /// reloaded once both of them have changed, e.g. after a renewal. A private key that doesn't
+    /// match the certificate is not loaded. Connections that are already open keep using the
+    /// previous certificate.
+    pub fn from_pem_file(
+        cert: impl Into<PathBuf>,
+        key: impl Into<PathBuf>,
+    ) -> Result<Self, crate::Error> {
+        let mut files = CertificateFiles {
+            cert: cert.into(),
+            key: key.into(),
+            reload_interval: DEFAULT_RELOAD_INTERVAL,
+            loaded: [None; 2],
+        };
+        // Taken before reading, so changes made while loading are picked up later on
+        files.loaded = files.modified();
+        let config = files.load()?;
+        Ok(Self {
+            config: RustlsConfig::from_config(Arc::new(config)),
+            files: Some(files),
+        })
+    }
+
+    /// Set how often certificate files are checked for changes. Defaults to 10 seconds.
+    ///
+    /// Has no effect on configurations created with [`TlsConfig::from_pem`].
+    pub fn reload_interval(mut self, interval: Duration) -> Self {
+        if let Some(files) = &mut self.files {
+            files.reload_interval = interval;
+        }
+        self
+    }
+
+    /// Reloads the certificate whenever both of its files change, for as long as the future is
+    /// polled
+    pub(crate) async fn watch(self) {
+        let Some(files) = self.files else {
+            return std::future::pending().await;
+        };
+        let mut loaded = files.loaded;
+        // Modification times of the last files tried, to not retry them if they are invalid
+        let mut attempted = loaded;
+        let mut interval = tokio::time::interval(files.reload_interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            let modified = files.modified();
+            // Renewals replace the files one at a time, so wait for both of them
+            let both_changed = modified[0] != loaded[0] && modified[1] != loaded[1];
+            if !both_changed || modified == attempted {
+                continue;
+            }
+            attempted = modified;
+            match files.load() {
+                Ok(config) => {
+                    self.config.reload_from_config(Arc::new(config));
+                    loaded = modified;
+                    info!("reloaded TLS certificate from {:?}", files.cert);
+                }
+                Err(e) => warn!("keeping the previous TLS certificate, reloading failed: {e}"),
+            }
+        }
+    }
+}
+
+/// Paths to a PEM encoded certificate chain and private key
+#[derive(Debug, Clone)]
+pub(crate) struct CertificateFiles {
+    cert: PathBuf,
+    key: PathBuf,
+    reload_interval: Duration,
+    /// Modification times of the files being served
+    loaded: [Option<SystemTime>; 2],
+}
+
+impl CertificateFiles {
+    fn load(&self) -> Result<ServerConfig, crate::Error> {
+        let cert = read(&self.cert)?;
+        let key = read(&self.key)?;
+        server_config(&cert, &key)
+    }
+
+    /// Modification times of the files, or `None` for files that can't be read right now
+    fn modified(&self) -> [Option<SystemTime>; 2] {
+        let modified = |path: &Path| path.metadata().and_then(|meta| meta.modified()).ok();
+        [modified(&self.cert), modified(&self.key)]
+    }
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, crate::Error> {
+    std::fs::read(path).map_err(|e| crate::Error::Tls(format!("reading {path:?}: {e}")))
+}
+
+fn server_config(cert: &[u8], key: &[u8]) -> Result<ServerConfig, crate::Error> {
+    let invalid = |e: io::Error| crate::Error::Tls(e.to_string());
+    let chain: Vec<_> = rustls_pemfile::certs(&mut &*cert)
+        .map_err(invalid)?
+        .into_iter()
+        .map(Certificate)
+        .collect();
+    if chain.is_empty() {
+        return Err(crate::Error::Tls("no certificate found".to_string()));
+    }
+    let key = rustls_pemfile::read_all(&mut &*key)
+        .map_err(invalid)?
+        .into_iter()
+        .find_map(|item| match item {
+            Item::RSAKey(key) | Item::PKCS8Key(key) | Item::ECKey(key) => Some(PrivateKey(key)),
+            _ => None,
+        })
+        .ok_or_else(|| crate::Error::Tls("no private key found".to_string()))?;
+    // rustls doesn't check that the key belongs to the certificate
+    verify_key_matches(&chain[0], &key)?;
+
+    let mut config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(chain, key)
+        .map_err(|e| crate::Error::Tls(e.to_string()))?;
+    // Websockets are upgraded from HTTP/1.1 connections
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Ok(config)
+}
+
+/// Signature schemes to check keys with, and how to verify their signatures
+const KEY_CHECK_SCHEMES: [(SignatureScheme, &webpki::SignatureAlgorithm); 4] = [
+    (
+        SignatureScheme::ECDSA_NISTP256_SHA256,
+        &webpki::ECDSA_P256_SHA256,
+    ),
+    (
+        SignatureScheme::ECDSA_NISTP384_SHA384,
+        &webpki::ECDSA_P384_SHA384,
+    ),
+    (SignatureScheme::ED25519, &webpki::ED25519),
+    (
+        SignatureScheme::RSA_PKCS1_SHA256,
+        &webpki::RSA_PKCS1_2048_8192_SHA256,
+    ),
+];
+
+/// Checks that the private key belongs to the certificate, by signing a message with the key and
+/// verifying the signature with the certificate's public key.
+fn verify_key_matches(cert: &Certificate, key: &PrivateKey) -> Result<(), crate::Error> {
+    let signing_key =
+        rustls::sign::any_supported_type(key).map_err(|e| crate::Error::Tls(e.to_string()))?;
+    let schemes = KEY_CHECK_SCHEMES.map(|(scheme, _)| scheme);
+    let signer = signing_key
+        .choose_scheme(&schemes)
+        .ok_or_else(|| crate::Error::Tls("unsupported private key type".to_string()))?;
+    let (_, algorithm) = KEY_CHECK_SCHEMES
+        .into_iter()
+        .find(|(scheme, _)| *scheme == signer.scheme())
+        .expect("signer for an offered scheme");
+
+    let message = b"matchbox certificate key check";
+    let signature = signer
+        .sign(message)
+        .map_err(|e| crate::Error::Tls(e.to_string()))?;
+    webpki::EndEntityCert::try_from(cert.0.as_slice())
+        .map_err(|e| crate::Error::Tls(format!("invalid certificate: {e}")))?
+        .verify_signature(algorithm, message, &signature)
+        .map_err(|_| crate::Error::Tls("private key does not match the certificate".to_string()))
+}
@@ -12,7 +12,7 @@ use crate::{
 };
 use async_trait::async_trait;
 use axum::extract::ws::Message;
-use futures::StreamExt;
+use futures::{Future, StreamExt};
 use matchbox_protocol::{JsonSignalEvent, PeerEvent, PeerId, PeerRequest};
 use std::collections::HashMap;
 use tracing::{error, info, warn};
@@ -31,6 +31,18 @@ impl SignalingServerBuilder<ClientServer, ClientServerCallbacks, ClientServerSta
         self
     }
 
+    /// Set an asynchronous callback triggered on all client websocket connections.
+    ///
+    /// The client's messages are handled once the callback completes.
+    pub fn on_client_connected_async<F, Fut>(mut self, callback: F) -> Self
+    where
+        F: Fn(PeerId) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.callbacks.on_client_connected = Callback::from_async(callback);
+        self
+    }
+
     /// Set a callback triggered on all client websocket disconnections.
     pub fn on_client_disconnected<F>(mut self, callback: F) -> Self
     where
@@ -40,6 +52,16 @@ impl SignalingServerBuilder<ClientServer, ClientServerCallbacks, ClientServerSta
         self
     }
 
+    /// Set an asynchronous callback triggered on all client websocket disconnections.
+    pub fn on_client_disconnected_async<F, Fut>(mut self, callback: F) -> Self
+    where
+        F: Fn(PeerId) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.callbacks.on_client_disconnected = Callback::from_async(callback);
+        self
+    }
+
     /// Set a callback triggered on host websocket connection.
     pub fn on_host_connected<F>(mut self, callback: F) -> Self
     where
@@ -49,6 +71,18 @@ impl SignalingServerBuilder<ClientServer, ClientServerCallbacks, ClientServerSta
         self
     }
 
+    /// Set an asynchronous callback triggered on host websocket connection.
+    ///
+    /// The host's messages are handled once the callback completes.
+    pub fn on_host_connected_async<F, Fut>(mut self, callback: F) -> Self
+    where
+        F: Fn(PeerId) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.callbacks.on_host_connected = Callback::from_async(callback);
+        self
+    }
+
     /// Set a callback triggered on host websocket disconnection.
     pub fn on_host_disconnected<F>(mut self, callback: F) -> Self
     where
@@ -57,6 +91,16 @@ impl SignalingServerBuilder<ClientServer, ClientServerCallbacks, ClientServerSta
         self.callbacks.on_host_disconnected = Callback::from(callback);
         self
     }
+
+    /// Set an asynchronous callback triggered on host websocket disconnection.
+    pub fn on_host_disconnected_async<F, Fut>(mut self, callback: F) -> Self
+    where
+        F: Fn(PeerId) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.callbacks.on_host_disconnected = Callback::from_async(callback);
+        self
+    }
 }
 
 #[async_trait]
@@ -66,16 +110,20 @@ impl SignalingTopology<ClientServerCallbacks, ClientServerState> for ClientServe
             peer_id,
             sender,
             mut receiver,
+            mut session,
             mut state,
             callbacks,
         } = upgrade;
 
-        // The first person to connect becomes host.
-        if state.get_host().is_none() {
+        if session.is_resumed() {
+            // The host or clients already know about this peer, only swap the channel
+            state.resume_peer(peer_id, sender.clone());
+        } else if state.get_host().is_none() {
+            // The first person to connect becomes host.
             // Set host
             state.set_host(peer_id, sender.clone());
             // Lifecycle event: On Host Connected
-            callbacks.on_host_connected.emit(peer_id);
+            callbacks.on_host_connected.emit(peer_id).await;
         } else {
             // Alert server of new user
             let event = Message::Text(JsonSignalEvent::Peer(PeerEvent::NewPeer(peer_id)).to_string());
@@ -85,7 +133,7 @@ impl SignalingTopology<ClientServerCallbacks, ClientServerState> for ClientServe
                     // Add peer to state
                     state.add_client(peer_id, sender.clone());
                     // Lifecycle event: On Client Connected
-                    callbacks.on_client_connected.emit(peer_id);
+                    callbacks.on_client_connected.emit(peer_id).await;
                 }
                 Err(e) => {
                     error!("error sending peer {peer_id} to host: {e:?}");
@@ -100,8 +148,21 @@ impl SignalingTopology<ClientServerCallbacks, ClientServerState> for ClientServe
             host.is_some() && host.unwrap() == peer_id
         };
 
+        // Whether the peer left on purpose, instead of losing its connection
+        let mut left = false;
+
         // The state machine for the data channel established for this websocket.
-        while let Some(request) = receiver.next().await {
+        loop {
+            let request = tokio::select! {
+                request = receiver.next() => request,
+                _ = session.superseded() => {
+                    info!("Session of {peer_id} resumed on a new connection");
+                    return;
+                }
+            };
+            let Some(request) = request else {
+                break;
+            };
             let request = match parse_request(request) {
                 Ok(request) => request,
                 Err(e) => {
@@ -118,16 +179,7 @@ impl SignalingTopology<ClientServerCallbacks, ClientServerState> for ClientServe
                             continue; // Recoverable error
                         }
                     };
-                    if is_host {
-                        state.reset();
-                        // Lifecycle event: On Host Disonnected
-                        callbacks.on_host_disconnected.emit(peer_id);
-                    } else {
-                        state.remove_client(&peer_id);
-                        // Lifecycle event: On Client Disonnected
-                        callbacks.on_client_disconnected.emit(peer_id);
-                    }
-                    return;
+                    break;
                 }
             };
 
@@ -153,18 +205,30 @@ impl SignalingTopology<ClientServerCallbacks, ClientServerState> for ClientServe
                 PeerRequest::KeepAlive => {
                     // Do nothing. KeepAlive packets are used to protect against idle websocket
                     // connections getting automatically disconnected, common for reverse proxies.
+                    // Receiving them already counts as activity for the idle timeout.
+                }
+                PeerRequest::Leave => {
+                    info!("{peer_id} left");
+                    left = true;
+                    break;
                 }
             }
         }
 
+        // Give the peer a chance to resume its session before telling anyone it left
+        if !left && receiver.may_resume() && session.hold().await {
+            info!("Session of {peer_id} resumed on a new connection");
+            return;
+        }
+
         if is_host {
             state.reset();
             // Lifecycle event: On Host Disonnected
-            callbacks.on_host_disconnected.emit(peer_id);
+            callbacks.on_host_disconnected.emit(peer_id).await;
         } else {
             state.remove_client(&peer_id);
             // Lifecycle event: On Client Disonnected
-            callbacks.on_client_disconnected.emit(peer_id);
+            callbacks.on_client_disconnected.emit(peer_id).await;
         }
     }
 }
@@ -207,6 +271,17 @@ impl ClientServerState {
         self.clients.lock().as_mut().unwrap().insert(peer, sender);
     }
 
+    /// Replace the channel of a host or client that resumed its session, without alerting anyone
+    pub fn resume_peer(&mut self, peer: PeerId, sender: SignalingChannel) {
+        let mut host = self.host.lock().unwrap();
+        match host.as_mut() {
+            Some((host_id, host_sender)) if *host_id == peer => *host_sender = sender,
+            _ => {
+                self.clients.lock().as_mut().unwrap().insert(peer, sender);
+            }
+        }
+    }
+
     /// Remove a client from the state if it existed.
     pub fn remove_client(&mut self, peer_id: &PeerId) {
         // Tell host about disconnected clent
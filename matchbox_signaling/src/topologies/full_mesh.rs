@@ -12,7 +12,7 @@ use crate::{
 };
 use async_trait::async_trait;
 use axum::extract::ws::Message;
-use futures::StreamExt;
+use futures::{Future, StreamExt};
 use matchbox_protocol::{JsonSignalEvent, PeerEvent, PeerId, PeerRequest};
 use std::collections::HashMap;
 use tracing::{error, info, warn};
@@ -31,6 +31,18 @@ impl SignalingServerBuilder<FullMesh, FullMeshCallbacks, FullMeshState> {
         self
     }
 
+    /// Set an asynchronous callback triggered on all websocket connections.
+    ///
+    /// The peer's messages are handled once the callback completes.
+    pub fn on_peer_connected_async<F, Fut>(mut self, callback: F) -> Self
+    where
+        F: Fn(PeerId) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.callbacks.on_peer_connected = Callback::from_async(callback);
+        self
+    }
+
     /// Set a callback triggered on all websocket disconnections.
     pub fn on_peer_disconnected<F>(mut self, callback: F) -> Self
     where
@@ -39,6 +51,16 @@ impl SignalingServerBuilder<FullMesh, FullMeshCallbacks, FullMeshState> {
         self.callbacks.on_peer_disconnected = Callback::from(callback);
         self
     }
+
+    /// Set an asynchronous callback triggered on all websocket disconnections.
+    pub fn on_peer_disconnected_async<F, Fut>(mut self, callback: F) -> Self
+    where
+        F: Fn(PeerId) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.callbacks.on_peer_disconnected = Callback::from_async(callback);
+        self
+    }
 }
 
 #[async_trait]
@@ -49,16 +71,35 @@ impl SignalingTopology<FullMeshCallbacks, FullMeshState> for FullMesh {
             peer_id,
             sender,
             mut receiver,
+            mut session,
             mut state,
             callbacks,
         } = upgrade;
-        // Add peer to state
-        state.add_peer(peer_id, sender.clone());
-        // Lifecycle event: On Connected
-        callbacks.on_peer_connected.emit(peer_id);
+        if session.is_resumed() {
+            // Other peers already know about this peer, only swap the channel
+            state.resume_peer(peer_id, sender.clone());
+        } else {
+            // Add peer to state
+            state.add_peer(peer_id, sender.clone());
+            // Lifecycle event: On Connected
+            callbacks.on_peer_connected.emit(peer_id).await;
+        }
+
+        // Whether the peer left on purpose, instead of losing its connection
+        let mut left = false;
 
         // The state machine for the data channel established for this websocket.
-        while let Some(request) = receiver.next().await {
+        loop {
+            let request = tokio::select! {
+                request = receiver.next() => request,
+                _ = session.superseded() => {
+                    info!("Session of {peer_id} resumed on a new connection");
+                    return;
+                }
+            };
+            let Some(request) = request else {
+                break;
+            };
             let request = match parse_request(request) {
                 Ok(request) => request,
                 Err(e) => {
@@ -75,10 +116,7 @@ impl SignalingTopology<FullMeshCallbacks, FullMeshState> for FullMesh {
                             continue; // Recoverable error
                         }
                     };
-                    state.remove_peer(&peer_id);
-                    // Lifecycle event: On Disonnected
-                    callbacks.on_peer_disconnected.emit(peer_id);
-                    return;
+                    break;
                 }
             };
 
@@ -98,14 +136,26 @@ impl SignalingTopology<FullMeshCallbacks, FullMeshState> for FullMesh {
                 PeerRequest::KeepAlive => {
                     // Do nothing. KeepAlive packets are used to protect against idle websocket
                     // connections getting automatically disconnected, common for reverse proxies.
+                    // Receiving them already counts as activity for the idle timeout.
+                }
+                PeerRequest::Leave => {
+                    info!("{peer_id} left");
+                    left = true;
+                    break;
                 }
             }
         }
 
+        // Give the peer a chance to resume its session before telling everyone it left
+        if !left && receiver.may_resume() && session.hold().await {
+            info!("Session of {peer_id} resumed on a new connection");
+            return;
+        }
+
         // Peer disconnected or otherwise ended communication.
         state.remove_peer(&peer_id);
         // Lifecycle event: On Disconnected
-        callbacks.on_peer_disconnected.emit(peer_id);
+        callbacks.on_peer_disconnected.emit(peer_id).await;
     }
 }
 
@@ -142,6 +192,11 @@ impl FullMeshState {
         self.peers.lock().as_mut().unwrap().insert(peer, sender);
     }
 
+    /// Replace the channel of a peer that resumed its session, without alerting other peers
+    pub fn resume_peer(&mut self, peer: PeerId, sender: SignalingChannel) {
+        self.peers.lock().as_mut().unwrap().insert(peer, sender);
+    }
+
     /// Remove a peer from the state if it existed, returning the peer removed.
     pub fn remove_peer(&mut self, peer_id: &PeerId) {
         let removed_peer = self
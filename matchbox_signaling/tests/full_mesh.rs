@@ -1,14 +1,33 @@
 #[cfg(test)]
 mod tests {
-    use futures::{SinkExt, StreamExt};
-    use matchbox_protocol::{JsonSignalEvent, PeerEvent, PeerId};
-    use matchbox_signaling::SignalingServer;
-    use std::{net::Ipv4Addr, str::FromStr};
+    use futures::{pin_mut, SinkExt, StreamExt};
+    use matchbox_protocol::{
+        JsonPeerRequest, JsonSignalEvent, PeerEvent, PeerId, ResumeToken, AUTH_TOKEN_QUERY_PARAM,
+        RESUMABLE_QUERY_PARAM, RESUME_TOKEN_QUERY_PARAM,
+    };
+    use matchbox_signaling::{LimitExceeded, SignalingServer};
+    use std::{
+        net::Ipv4Addr,
+        str::FromStr,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
     use tokio::{
         net::TcpStream,
-        sync::mpsc::{error::TryRecvError, unbounded_channel},
+        select,
+        sync::{
+            mpsc::{error::TryRecvError, unbounded_channel},
+            Notify,
+        },
+        time,
+    };
+    use tokio_tungstenite::{
+        tungstenite::{client::IntoClientRequest, protocol::frame::coding::CloseCode, Message},
+        MaybeTlsStream, WebSocketStream,
     };
-    use tokio_tungstenite::{tungstenite::Message, MaybeTlsStream, WebSocketStream};
 
     // Helper to take the next PeerEvent from a stream
     async fn recv_peer_event(
@@ -27,6 +46,15 @@ mod tests {
         }
     }
 
+    // Helper to extract the ResumeToken when expecting one
+    fn get_resume_token(peer_event: JsonSignalEvent) -> ResumeToken {
+        if let JsonSignalEvent::Peer(PeerEvent::ResumeToken(token)) = peer_event {
+            token
+        } else {
+            panic!("Peer_event was not ResumeToken: {peer_event:?}");
+        }
+    }
+
     #[tokio::test]
     async fn ws_connect() {
         let server = SignalingServer::full_mesh_builder((Ipv4Addr::LOCALHOST, 0)).build();
@@ -208,6 +236,80 @@ mod tests {
         assert_eq!(peer_connected_rx.try_recv(), Err(TryRecvError::Empty));
     }
 
+    #[tokio::test]
+    async fn async_connection_request_callback() {
+        let release = Arc::new(Notify::new());
+        let server = SignalingServer::full_mesh_builder((Ipv4Addr::LOCALHOST, 0))
+            .on_connection_request_async({
+                let release = release.clone();
+                move |meta| {
+                    let release = release.clone();
+                    async move {
+                        // Hold up connections to the slow room until released
+                        if meta.path.as_deref() == Some("slow") {
+                            release.notified().await;
+                        }
+                        Ok(meta.query_params.contains_key("allow"))
+                    }
+                }
+            })
+            .build();
+        let addr = server.local_addr();
+        tokio::spawn(server.serve());
+
+        let slow = tokio::spawn(tokio_tungstenite::connect_async(format!(
+            "ws://{addr}/slow?allow"
+        )));
+
+        // Other connections are not held up by the pending callback
+        let (mut client, _response) =
+            tokio_tungstenite::connect_async(format!("ws://{addr}/room_a?allow"))
+                .await
+                .unwrap();
+        let _uuid = get_peer_id(recv_peer_event(&mut client).await);
+        assert!(tokio_tungstenite::connect_async(format!("ws://{addr}/room_a"))
+            .await
+            .is_err());
+        assert!(!slow.is_finished());
+
+        release.notify_one();
+        let (mut slow_client, _response) = slow.await.unwrap().unwrap();
+        let _uuid = get_peer_id(recv_peer_event(&mut slow_client).await);
+    }
+
+    #[tokio::test]
+    async fn connection_request_sees_headers_and_token() {
+        let server = SignalingServer::full_mesh_builder((Ipv4Addr::LOCALHOST, 0))
+            .on_connection_request(|meta| {
+                let header = meta
+                    .headers
+                    .get("authorization")
+                    .and_then(|value| value.to_str().ok());
+                let token = meta.query_params.get(AUTH_TOKEN_QUERY_PARAM);
+                Ok(header == Some("Bearer secret") || token.is_some_and(|token| token == "s3cr/t"))
+            })
+            .build();
+        let addr = server.local_addr();
+        tokio::spawn(server.serve());
+
+        let mut request = format!("ws://{addr}/room_a").into_client_request().unwrap();
+        request
+            .headers_mut()
+            .insert("Authorization", "Bearer secret".parse().unwrap());
+        tokio_tungstenite::connect_async(request)
+            .await
+            .expect("handshake with header");
+
+        tokio_tungstenite::connect_async(format!(
+            "ws://{addr}/room_a?{AUTH_TOKEN_QUERY_PARAM}=s3cr%2Ft"
+        ))
+        .await
+        .expect("handshake with token");
+
+        let denied = tokio_tungstenite::connect_async(format!("ws://{addr}/room_a")).await;
+        assert!(denied.is_err());
+    }
+
     #[tokio::test]
     async fn on_id_assignment_callback() {
         let (id_assigned_tx, mut id_assigned_rx) = unbounded_channel();
@@ -275,4 +377,493 @@ mod tests {
 
         disconnected_rx.recv().await.expect("disconnected");
     }
+
+    #[tokio::test]
+    async fn resume_session() {
+        let server = SignalingServer::full_mesh_builder((Ipv4Addr::LOCALHOST, 0))
+            .session_resume_grace_period(Duration::from_secs(5))
+            .build();
+        let addr = server.local_addr();
+        tokio::spawn(server.serve());
+
+        let (mut client_a, _response) =
+            tokio_tungstenite::connect_async(format!("ws://{addr}/room_a"))
+                .await
+                .unwrap();
+
+        let _a_uuid = get_peer_id(recv_peer_event(&mut client_a).await);
+
+        let (mut client_b, _response) = tokio_tungstenite::connect_async(format!(
+            "ws://{addr}/room_a?{RESUMABLE_QUERY_PARAM}=true"
+        ))
+        .await
+        .unwrap();
+
+        let b_uuid = get_peer_id(recv_peer_event(&mut client_b).await);
+        let b_token = get_resume_token(recv_peer_event(&mut client_b).await);
+
+        // Ensure Peer B was received
+        let new_peer_event = recv_peer_event(&mut client_a).await;
+        assert_eq!(new_peer_event, JsonSignalEvent::Peer(PeerEvent::NewPeer(b_uuid)));
+
+        // Disconnect Peer B, and reconnect with its resume token
+        _ = client_b.close(None).await;
+        let (mut client_b, _response) = tokio_tungstenite::connect_async(format!(
+            "ws://{addr}/room_a?{RESUME_TOKEN_QUERY_PARAM}={b_token}"
+        ))
+        .await
+        .unwrap();
+
+        let resumed_b_uuid = get_peer_id(recv_peer_event(&mut client_b).await);
+        assert_eq!(resumed_b_uuid, b_uuid);
+
+        // Peer A should neither be told that B left, nor that it joined again
+        let timeout = time::sleep(Duration::from_millis(100));
+        pin_mut!(timeout);
+        select! {
+            event = client_a.next() => panic!("unexpected message: {event:?}"),
+            _ = &mut timeout => {}
+        }
+    }
+
+    #[tokio::test]
+    async fn resume_session_after_grace_period() {
+        let server = SignalingServer::full_mesh_builder((Ipv4Addr::LOCALHOST, 0))
+            .session_resume_grace_period(Duration::from_millis(10))
+            .build();
+        let addr = server.local_addr();
+        tokio::spawn(server.serve());
+
+        let (mut client_a, _response) =
+            tokio_tungstenite::connect_async(format!("ws://{addr}/room_a"))
+                .await
+                .unwrap();
+
+        let _a_uuid = get_peer_id(recv_peer_event(&mut client_a).await);
+
+        let (mut client_b, _response) = tokio_tungstenite::connect_async(format!(
+            "ws://{addr}/room_a?{RESUMABLE_QUERY_PARAM}=true"
+        ))
+        .await
+        .unwrap();
+
+        let b_uuid = get_peer_id(recv_peer_event(&mut client_b).await);
+        let b_token = get_resume_token(recv_peer_event(&mut client_b).await);
+
+        // Ensure Peer B was received
+        let new_peer_event = recv_peer_event(&mut client_a).await;
+        assert_eq!(new_peer_event, JsonSignalEvent::Peer(PeerEvent::NewPeer(b_uuid)));
+
+        // Disconnect Peer B, and wait for the grace period to expire
+        _ = client_b.close(None).await;
+        let peer_left_event = recv_peer_event(&mut client_a).await;
+        assert_eq!(peer_left_event, JsonSignalEvent::Peer(PeerEvent::PeerLeft(b_uuid)));
+
+        // The expired token gets a new id
+        let (mut client_b, _response) = tokio_tungstenite::connect_async(format!(
+            "ws://{addr}/room_a?{RESUME_TOKEN_QUERY_PARAM}={b_token}"
+        ))
+        .await
+        .unwrap();
+
+        let new_b_uuid = get_peer_id(recv_peer_event(&mut client_b).await);
+        assert_ne!(new_b_uuid, b_uuid);
+    }
+
+    #[tokio::test]
+    async fn resume_abandoned_before_upgrade() {
+        // The third connection, which resumes Peer B's session, never gets past ID assignment
+        let assignments = Arc::new(AtomicUsize::new(0));
+        let resuming = Arc::new(Notify::new());
+        let server = SignalingServer::full_mesh_builder((Ipv4Addr::LOCALHOST, 0))
+            .session_resume_grace_period(Duration::from_millis(200))
+            .on_id_assignment_async({
+                let resuming = resuming.clone();
+                move |_| {
+                    let resuming = resuming.clone();
+                    let assignment = assignments.fetch_add(1, Ordering::SeqCst);
+                    async move {
+                        if assignment == 2 {
+                            resuming.notify_one();
+                            std::future::pending::<()>().await;
+                        }
+                    }
+                }
+            })
+            .build();
+        let addr = server.local_addr();
+        tokio::spawn(server.serve());
+
+        let (mut client_a, _response) =
+            tokio_tungstenite::connect_async(format!("ws://{addr}/room_a"))
+                .await
+                .unwrap();
+
+        let _a_uuid = get_peer_id(recv_peer_event(&mut client_a).await);
+
+        let (mut client_b, _response) = tokio_tungstenite::connect_async(format!(
+            "ws://{addr}/room_a?{RESUMABLE_QUERY_PARAM}=true"
+        ))
+        .await
+        .unwrap();
+
+        let b_uuid = get_peer_id(recv_peer_event(&mut client_b).await);
+        let b_token = get_resume_token(recv_peer_event(&mut client_b).await);
+
+        // Ensure Peer B was received
+        let new_peer_event = recv_peer_event(&mut client_a).await;
+        assert_eq!(new_peer_event, JsonSignalEvent::Peer(PeerEvent::NewPeer(b_uuid)));
+
+        // Disconnect Peer B, and give up on resuming before the upgrade completes
+        _ = client_b.close(None).await;
+        let resume = tokio::spawn(tokio_tungstenite::connect_async(format!(
+            "ws://{addr}/room_a?{RESUME_TOKEN_QUERY_PARAM}={b_token}"
+        )));
+        resuming.notified().await;
+        resume.abort();
+
+        // Peer B is removed once the grace period expires
+        let peer_left_event = time::timeout(Duration::from_secs(2), recv_peer_event(&mut client_a))
+            .await
+            .expect("peer left");
+        assert_eq!(peer_left_event, JsonSignalEvent::Peer(PeerEvent::PeerLeft(b_uuid)));
+    }
+
+    #[tokio::test]
+    async fn resume_token_only_sent_when_asked() {
+        let server = SignalingServer::full_mesh_builder((Ipv4Addr::LOCALHOST, 0))
+            .session_resume_grace_period(Duration::from_secs(5))
+            .build();
+        let addr = server.local_addr();
+        tokio::spawn(server.serve());
+
+        let (mut client_a, _response) =
+            tokio_tungstenite::connect_async(format!("ws://{addr}/room_a"))
+                .await
+                .unwrap();
+
+        let _a_uuid = get_peer_id(recv_peer_event(&mut client_a).await);
+
+        let (mut client_b, _response) =
+            tokio_tungstenite::connect_async(format!("ws://{addr}/room_a"))
+                .await
+                .unwrap();
+
+        let b_uuid = get_peer_id(recv_peer_event(&mut client_b).await);
+
+        // Peer A is told about Peer B right away, without a resume token in between
+        let new_peer_event = recv_peer_event(&mut client_a).await;
+        assert_eq!(new_peer_event, JsonSignalEvent::Peer(PeerEvent::NewPeer(b_uuid)));
+    }
+
+    #[tokio::test]
+    async fn resume_token_is_bound_to_room() {
+        let server = SignalingServer::full_mesh_builder((Ipv4Addr::LOCALHOST, 0))
+            .session_resume_grace_period(Duration::from_secs(5))
+            .build();
+        let addr = server.local_addr();
+        tokio::spawn(server.serve());
+
+        let (mut client_a, _response) = tokio_tungstenite::connect_async(format!(
+            "ws://{addr}/room_a?{RESUMABLE_QUERY_PARAM}=true"
+        ))
+        .await
+        .unwrap();
+
+        let a_uuid = get_peer_id(recv_peer_event(&mut client_a).await);
+        let a_token = get_resume_token(recv_peer_event(&mut client_a).await);
+
+        // Presenting the token in another room gets a new id
+        _ = client_a.close(None).await;
+        let (mut client_a, _response) = tokio_tungstenite::connect_async(format!(
+            "ws://{addr}/room_b?{RESUME_TOKEN_QUERY_PARAM}={a_token}"
+        ))
+        .await
+        .unwrap();
+
+        let new_a_uuid = get_peer_id(recv_peer_event(&mut client_a).await);
+        assert_ne!(new_a_uuid, a_uuid);
+    }
+
+    #[tokio::test]
+    async fn leave_ends_session_right_away() {
+        let server = SignalingServer::full_mesh_builder((Ipv4Addr::LOCALHOST, 0))
+            .session_resume_grace_period(Duration::from_secs(60))
+            .build();
+        let addr = server.local_addr();
+        tokio::spawn(server.serve());
+
+        let (mut client_a, _response) =
+            tokio_tungstenite::connect_async(format!("ws://{addr}/room_a"))
+                .await
+                .unwrap();
+
+        let _a_uuid = get_peer_id(recv_peer_event(&mut client_a).await);
+
+        let (mut client_b, _response) = tokio_tungstenite::connect_async(format!(
+            "ws://{addr}/room_a?{RESUMABLE_QUERY_PARAM}=true"
+        ))
+        .await
+        .unwrap();
+
+        let b_uuid = get_peer_id(recv_peer_event(&mut client_b).await);
+        let b_token = get_resume_token(recv_peer_event(&mut client_b).await);
+
+        // Ensure Peer B was received
+        let new_peer_event = recv_peer_event(&mut client_a).await;
+        assert_eq!(new_peer_event, JsonSignalEvent::Peer(PeerEvent::NewPeer(b_uuid)));
+
+        // Peer B leaves, which Peer A is told about without waiting for the grace period
+        client_b
+            .send(Message::Text(JsonPeerRequest::Leave.to_string()))
+            .await
+            .unwrap();
+        let peer_left_event = time::timeout(Duration::from_secs(5), recv_peer_event(&mut client_a))
+            .await
+            .expect("peer left before the grace period");
+        assert_eq!(peer_left_event, JsonSignalEvent::Peer(PeerEvent::PeerLeft(b_uuid)));
+
+        // The session can't be resumed anymore
+        let (mut client_b, _response) = tokio_tungstenite::connect_async(format!(
+            "ws://{addr}/room_a?{RESUME_TOKEN_QUERY_PARAM}={b_token}"
+        ))
+        .await
+        .unwrap();
+
+        let new_b_uuid = get_peer_id(recv_peer_event(&mut client_b).await);
+        assert_ne!(new_b_uuid, b_uuid);
+    }
+
+    #[tokio::test]
+    async fn shutdown_closes_connections() {
+        let (disconnected_tx, mut disconnected_rx) = unbounded_channel::<PeerId>();
+
+        let server = SignalingServer::full_mesh_builder((Ipv4Addr::LOCALHOST, 0))
+            .session_resume_grace_period(Duration::from_secs(60))
+            .on_peer_disconnected(move |peer_id| {
+                disconnected_tx.send(peer_id).expect("send disconnected");
+            })
+            .build();
+        let addr = server.local_addr();
+        let shutdown = server.shutdown_handle();
+        let serve = tokio::spawn(server.serve());
+
+        let (mut client, _response) =
+            tokio_tungstenite::connect_async(format!("ws://{addr}/room_a?{RESUMABLE_QUERY_PARAM}"))
+                .await
+                .unwrap();
+        let uuid = get_peer_id(recv_peer_event(&mut client).await);
+
+        shutdown.shutdown();
+        assert!(shutdown.is_shutdown());
+
+        // The peer only gets a close frame, which older clients understand as well
+        loop {
+            match client.next().await {
+                Some(Ok(Message::Close(Some(frame)))) => {
+                    assert_eq!(frame.code, CloseCode::Away);
+                    assert_eq!(frame.reason, "server shutting down");
+                    break;
+                }
+                // The resume token
+                Some(Ok(Message::Text(_))) => {}
+                message => panic!("expected a close frame: {message:?}"),
+            }
+        }
+
+        // The peer is removed like any other, without holding its session
+        let disconnected = time::timeout(Duration::from_secs(5), disconnected_rx.recv())
+            .await
+            .expect("disconnected before the grace period");
+        assert_eq!(disconnected, Some(uuid));
+
+        // The server returns without waiting for the session grace period
+        time::timeout(Duration::from_secs(5), serve)
+            .await
+            .expect("server stopped")
+            .unwrap()
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn idle_peer_times_out() {
+        let server = SignalingServer::full_mesh_builder((Ipv4Addr::LOCALHOST, 0))
+            .ping_interval(Duration::from_millis(50))
+            .idle_timeout(Duration::from_millis(300))
+            .build();
+        let addr = server.local_addr();
+        tokio::spawn(server.serve());
+
+        let (mut client_a, _response) =
+            tokio_tungstenite::connect_async(format!("ws://{addr}/room_a"))
+                .await
+                .unwrap();
+
+        let _a_uuid = get_peer_id(recv_peer_event(&mut client_a).await);
+
+        let (mut client_b, _response) =
+            tokio_tungstenite::connect_async(format!("ws://{addr}/room_a"))
+                .await
+                .unwrap();
+
+        let b_uuid = get_peer_id(recv_peer_event(&mut client_b).await);
+
+        // Peer B stops reading, so it never answers pings, while Peer A keeps answering them
+        let peer_left_event = time::timeout(Duration::from_secs(5), async {
+            loop {
+                match client_a.next().await.unwrap().unwrap() {
+                    Message::Ping(_) => continue,
+                    message => match JsonSignalEvent::from_str(&message.to_string()).unwrap() {
+                        JsonSignalEvent::Peer(PeerEvent::PeerLeft(id)) => break id,
+                        _ => continue,
+                    },
+                }
+            }
+        })
+        .await
+        .expect("idle peer removed");
+        assert_eq!(peer_left_event, b_uuid);
+    }
+
+    // Helper to take messages until the connection is closed, returning the close code
+    async fn recv_close_code(client: &mut WebSocketStream<MaybeTlsStream<TcpStream>>) -> u16 {
+        loop {
+            match client.next().await.unwrap().unwrap() {
+                Message::Close(Some(frame)) => break frame.code.into(),
+                _ => continue,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn connections_per_ip_limit() {
+        let (limit_tx, mut limit_rx) = unbounded_channel();
+        let server = SignalingServer::full_mesh_builder((Ipv4Addr::LOCALHOST, 0))
+            .max_connections_per_ip(1)
+            .on_limit_exceeded(move |violation| limit_tx.send(violation).unwrap())
+            .build();
+        let addr = server.local_addr();
+        tokio::spawn(server.serve());
+
+        let (mut client_a, _response) =
+            tokio_tungstenite::connect_async(format!("ws://{addr}/room_a"))
+                .await
+                .unwrap();
+        let _a_uuid = get_peer_id(recv_peer_event(&mut client_a).await);
+
+        let (mut client_b, _response) =
+            tokio_tungstenite::connect_async(format!("ws://{addr}/room_a"))
+                .await
+                .unwrap();
+        assert_eq!(recv_close_code(&mut client_b).await, 1013);
+        let violation = limit_rx.recv().await.unwrap();
+        assert_eq!(violation.limit, LimitExceeded::ConnectionsPerIp);
+        assert_eq!(violation.peer_id, None);
+
+        // The connection is no longer counted once closed
+        drop(client_a);
+        let connected = time::timeout(Duration::from_secs(5), async {
+            loop {
+                let (mut client, _response) =
+                    tokio_tungstenite::connect_async(format!("ws://{addr}/room_a"))
+                        .await
+                        .unwrap();
+                if let Message::Text(_) = client.next().await.unwrap().unwrap() {
+                    break;
+                }
+                time::sleep(Duration::from_millis(10)).await;
+            }
+        });
+        connected.await.expect("connection allowed again");
+    }
+
+    #[tokio::test]
+    async fn message_rate_limit() {
+        let (limit_tx, mut limit_rx) = unbounded_channel();
+        let server = SignalingServer::full_mesh_builder((Ipv4Addr::LOCALHOST, 0))
+            .max_messages_per_second(3)
+            .on_limit_exceeded(move |violation| limit_tx.send(violation).unwrap())
+            .build();
+        let addr = server.local_addr();
+        tokio::spawn(server.serve());
+
+        let (mut client_a, _response) =
+            tokio_tungstenite::connect_async(format!("ws://{addr}/room_a"))
+                .await
+                .unwrap();
+        let a_uuid = get_peer_id(recv_peer_event(&mut client_a).await);
+
+        for _ in 0..4 {
+            client_a
+                .send(Message::Text(JsonPeerRequest::KeepAlive.to_string()))
+                .await
+                .unwrap();
+        }
+        assert_eq!(recv_close_code(&mut client_a).await, 1008);
+        let violation = limit_rx.recv().await.unwrap();
+        assert_eq!(violation.limit, LimitExceeded::MessageRate);
+        assert_eq!(violation.peer_id, Some(a_uuid));
+    }
+
+    #[tokio::test]
+    async fn message_size_limit() {
+        let server = SignalingServer::full_mesh_builder((Ipv4Addr::LOCALHOST, 0))
+            .max_message_size(64)
+            .build();
+        let addr = server.local_addr();
+        tokio::spawn(server.serve());
+
+        let (mut client_a, _response) =
+            tokio_tungstenite::connect_async(format!("ws://{addr}/room_a"))
+                .await
+                .unwrap();
+        let a_uuid = get_peer_id(recv_peer_event(&mut client_a).await);
+
+        let request = JsonPeerRequest::Signal {
+            receiver: a_uuid,
+            data: serde_json::Value::String("x".repeat(64)),
+        };
+        client_a
+            .send(Message::Text(request.to_string()))
+            .await
+            .unwrap();
+        assert_eq!(recv_close_code(&mut client_a).await, 1009);
+    }
+
+    #[tokio::test]
+    async fn peer_breaking_a_limit_is_not_held() {
+        let server = SignalingServer::full_mesh_builder((Ipv4Addr::LOCALHOST, 0))
+            .max_message_size(64)
+            .session_resume_grace_period(Duration::from_secs(60))
+            .build();
+        let addr = server.local_addr();
+        tokio::spawn(server.serve());
+
+        let (mut client_a, _response) =
+            tokio_tungstenite::connect_async(format!("ws://{addr}/room_a"))
+                .await
+                .unwrap();
+        let _a_uuid = get_peer_id(recv_peer_event(&mut client_a).await);
+
+        let (mut client_b, _response) =
+            tokio_tungstenite::connect_async(format!("ws://{addr}/room_a"))
+                .await
+                .unwrap();
+        let b_uuid = get_peer_id(recv_peer_event(&mut client_b).await);
+        let new_peer_event = recv_peer_event(&mut client_a).await;
+        assert_eq!(new_peer_event, JsonSignalEvent::Peer(PeerEvent::NewPeer(b_uuid)));
+
+        // Much larger than the limit, so the websocket stops reading it early
+        client_b
+            .send(Message::Text("x".repeat(64 * 1024)))
+            .await
+            .unwrap();
+        assert_eq!(recv_close_code(&mut client_b).await, 1009);
+
+        // Peer A is told right away, instead of after the grace period
+        let peer_left_event = time::timeout(Duration::from_secs(5), recv_peer_event(&mut client_a))
+            .await
+            .expect("peer left before the grace period");
+        assert_eq!(peer_left_event, JsonSignalEvent::Peer(PeerEvent::PeerLeft(b_uuid)));
+    }
 }
@@ -0,0 +1,186 @@
+#![cfg(feature = "tls")]
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+    use matchbox_protocol::{JsonSignalEvent, PeerEvent};
+    use matchbox_signaling::{SignalingServer, TlsConfig};
+    use std::{net::Ipv4Addr, net::SocketAddr, str::FromStr, sync::Arc, time::Duration};
+    use tokio::{net::TcpStream, time};
+    use tokio_rustls::{
+        client::TlsStream,
+        rustls::{Certificate, ClientConfig, RootCertStore, ServerName},
+        TlsConnector,
+    };
+    use tokio_tungstenite::{tungstenite::Message, WebSocketStream};
+
+    type Client = WebSocketStream<TlsStream<TcpStream>>;
+
+    // Helper to connect to a room, trusting only the given certificate
+    async fn connect(addr: SocketAddr, trusted: &rcgen::Certificate) -> Option<Client> {
+        let mut roots = RootCertStore::empty();
+        roots
+            .add(&Certificate(trusted.serialize_der().unwrap()))
+            .unwrap();
+        let config = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let domain = ServerName::try_from("localhost").unwrap();
+        let tcp = TcpStream::connect(addr).await.unwrap();
+        let tls = TlsConnector::from(Arc::new(config))
+            .connect(domain, tcp)
+            .await
+            .ok()?;
+        let (client, _response) = tokio_tungstenite::client_async("wss://localhost/room_a", tls)
+            .await
+            .ok()?;
+        Some(client)
+    }
+
+    // Helper to take the next event and check that it is an id assignment
+    async fn expect_id_assigned(client: &mut Client) {
+        let message: Message = client.next().await.unwrap().unwrap();
+        let event = JsonSignalEvent::from_str(&message.to_string()).expect("json peer event");
+        assert!(matches!(
+            event,
+            JsonSignalEvent::Peer(PeerEvent::IdAssigned(..))
+        ));
+    }
+
+    // Helper to connect once the server serves the given certificate
+    async fn connect_when_served(addr: SocketAddr, cert: &rcgen::Certificate) -> Client {
+        time::timeout(Duration::from_secs(5), async {
+            loop {
+                if let Some(client) = connect(addr, cert).await {
+                    break client;
+                }
+                time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .expect("certificate served")
+    }
+
+    fn self_signed() -> rcgen::Certificate {
+        rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap()
+    }
+
+    fn temp_dir() -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("matchbox_tls_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn invalid_pem_is_rejected() {
+        let cert = self_signed();
+        let cert_pem = cert.serialize_pem().unwrap();
+        let key_pem = cert.serialize_private_key_pem();
+
+        assert!(TlsConfig::from_pem(cert_pem.as_bytes(), key_pem.as_bytes()).is_ok());
+        assert!(TlsConfig::from_pem(key_pem.as_bytes(), key_pem.as_bytes()).is_err());
+        assert!(TlsConfig::from_pem(cert_pem.as_bytes(), cert_pem.as_bytes()).is_err());
+        assert!(TlsConfig::from_pem_file("missing.pem", "missing.key").is_err());
+
+        // The key has to belong to the certificate
+        let other_key_pem = self_signed().serialize_private_key_pem();
+        assert!(TlsConfig::from_pem(cert_pem.as_bytes(), other_key_pem.as_bytes()).is_err());
+    }
+
+    #[tokio::test]
+    async fn wss_connect() {
+        let cert = self_signed();
+        let tls = TlsConfig::from_pem(
+            cert.serialize_pem().unwrap().as_bytes(),
+            cert.serialize_private_key_pem().as_bytes(),
+        )
+        .unwrap();
+        let server = SignalingServer::full_mesh_builder((Ipv4Addr::LOCALHOST, 0))
+            .tls(tls)
+            .build();
+        let addr = server.local_addr();
+        tokio::spawn(server.serve());
+
+        let mut client = connect(addr, &cert).await.expect("handshake");
+        expect_id_assigned(&mut client).await;
+
+        // The certificate is the only one trusted
+        assert!(connect(addr, &self_signed()).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn certificate_is_reloaded_when_files_change() {
+        let dir = temp_dir();
+        let (cert_path, key_path) = (dir.join("cert.pem"), dir.join("key.pem"));
+        let write = |cert: &rcgen::Certificate| {
+            std::fs::write(&cert_path, cert.serialize_pem().unwrap()).unwrap();
+            std::fs::write(&key_path, cert.serialize_private_key_pem()).unwrap();
+        };
+
+        let old_cert = self_signed();
+        write(&old_cert);
+        let tls = TlsConfig::from_pem_file(&cert_path, &key_path)
+            .unwrap()
+            .reload_interval(Duration::from_millis(50));
+        let server = SignalingServer::full_mesh_builder((Ipv4Addr::LOCALHOST, 0))
+            .tls(tls)
+            .build();
+        let addr = server.local_addr();
+        tokio::spawn(server.serve());
+
+        let mut client = connect(addr, &old_cert).await.expect("handshake");
+        expect_id_assigned(&mut client).await;
+
+        // Renew the certificate
+        let new_cert = self_signed();
+        write(&new_cert);
+
+        let mut client = connect_when_served(addr, &new_cert).await;
+        expect_id_assigned(&mut client).await;
+        assert!(connect(addr, &old_cert).await.is_none());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn certificate_is_only_reloaded_with_both_files_and_a_matching_key() {
+        let dir = temp_dir();
+        let (cert_path, key_path) = (dir.join("cert.pem"), dir.join("key.pem"));
+        let write_cert =
+            |cert: &rcgen::Certificate| std::fs::write(&cert_path, cert.serialize_pem().unwrap());
+        let write_key = |cert: &rcgen::Certificate| {
+            std::fs::write(&key_path, cert.serialize_private_key_pem())
+        };
+
+        let old_cert = self_signed();
+        write_cert(&old_cert).unwrap();
+        write_key(&old_cert).unwrap();
+        let tls = TlsConfig::from_pem_file(&cert_path, &key_path)
+            .unwrap()
+            .reload_interval(Duration::from_millis(20));
+        let server = SignalingServer::full_mesh_builder((Ipv4Addr::LOCALHOST, 0))
+            .tls(tls)
+            .build();
+        let addr = server.local_addr();
+        tokio::spawn(server.serve());
+
+        // Only the certificate has been renewed so far
+        let new_cert = self_signed();
+        write_cert(&new_cert).unwrap();
+        time::sleep(Duration::from_millis(200)).await;
+        assert!(connect(addr, &old_cert).await.is_some());
+
+        // Both files changed, but the key belongs to another certificate
+        write_key(&self_signed()).unwrap();
+        time::sleep(Duration::from_millis(200)).await;
+        assert!(connect(addr, &old_cert).await.is_some());
+
+        // The renewal is complete
+        write_key(&new_cert).unwrap();
+        let mut client = connect_when_served(addr, &new_cert).await;
+        expect_id_assigned(&mut client).await;
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
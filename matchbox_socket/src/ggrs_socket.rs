@@ -1,11 +1,12 @@
 use std::marker::PhantomData;
 
 use ggrs::{Message, PlayerType};
+use log::{error, warn};
 use matchbox_protocol::PeerId;
 
 use crate::{
-    ChannelConfig, MessageLoopFuture, MultipleChannels, NoChannels, Packet, SingleChannel,
-    WebRtcChannel, WebRtcSocket, WebRtcSocketBuilder,
+    Bincode, ChannelConfig, Codec, MessageLoopFuture, MultipleChannels, NoChannels, Packet,
+    SingleChannel, WebRtcChannel, WebRtcSocket, WebRtcSocketBuilder,
 };
 
 impl ChannelConfig {
@@ -92,29 +93,44 @@ impl WebRtcSocket {
     }
 }
 
-fn build_packet(msg: &Message) -> Packet {
-    bincode::serialize(&msg).unwrap().into_boxed_slice()
+fn send_message(channel: &mut WebRtcChannel, msg: &Message, peer: PeerId) {
+    match Bincode::encode(msg) {
+        Ok(packet) => channel.send(packet, peer),
+        Err(err) => error!("dropping ggrs message to {peer}: {err}"),
+    }
 }
 
-fn deserialize_packet(message: (PeerId, Packet)) -> (PeerId, Message) {
-    (message.0, bincode::deserialize(&message.1).unwrap())
+fn deserialize_packet((peer, packet): (PeerId, Packet)) -> Option<(PeerId, Message)> {
+    match Bincode::decode(&packet) {
+        Ok(msg) => Some((peer, msg)),
+        Err(err) => {
+            warn!("discarding malformed ggrs message from {peer}: {err}");
+            None
+        }
+    }
 }
 
 impl ggrs::NonBlockingSocket<PeerId> for WebRtcSocket<SingleChannel> {
     fn send_to(&mut self, msg: &Message, addr: &PeerId) {
-        self.send(build_packet(msg), *addr);
+        send_message(self.channel(0), msg, *addr);
     }
     fn receive_all_messages(&mut self) -> Vec<(PeerId, Message)> {
-        self.receive().into_iter().map(deserialize_packet).collect()
+        self.receive()
+            .into_iter()
+            .filter_map(deserialize_packet)
+            .collect()
     }
 }
 
 impl ggrs::NonBlockingSocket<PeerId> for WebRtcChannel {
     fn send_to(&mut self, msg: &Message, addr: &PeerId) {
-        self.send(build_packet(msg), *addr);
+        send_message(self, msg, *addr);
     }
 
     fn receive_all_messages(&mut self) -> Vec<(PeerId, Message)> {
-        self.receive().into_iter().map(deserialize_packet).collect()
+        self.receive()
+            .into_iter()
+            .filter_map(deserialize_packet)
+            .collect()
     }
 }
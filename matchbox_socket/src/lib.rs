@@ -2,15 +2,33 @@
 #![doc = include_str!("../README.md")]
 #![forbid(unsafe_code)]
 
+#[cfg(all(
+    not(target_arch = "wasm32"),
+    not(any(feature = "async-std", feature = "tokio"))
+))]
+compile_error!("matchbox_socket needs the `async-std` or `tokio` feature to run natively");
+
 mod error;
 #[cfg(feature = "ggrs")]
 mod ggrs_socket;
+mod typed_channel;
 mod webrtc_socket;
 
 pub use error::Error;
 pub use matchbox_protocol::{PeerId, SignalEvent, PeerEvent};
+#[cfg(feature = "bincode")]
+pub use typed_channel::Bincode;
+pub use typed_channel::{Codec, DecodeError, EncodeError, Json, TypedChannel, TypedSendError};
+#[cfg(all(feature = "loopback", not(target_arch = "wasm32")))]
+pub use webrtc_socket::LoopbackNetwork;
+#[cfg(not(target_arch = "wasm32"))]
+pub use webrtc_socket::{ClientCertificate, SignalingTlsConfig};
 pub use webrtc_socket::{
-    error::ChannelError, BuildablePlurality, ChannelConfig, ChannelPlurality, MessageLoopFuture,
-    MultipleChannels, NoChannels, Packet, PeerState, RtcIceServerConfig, SingleChannel,
-    WebRtcChannel, WebRtcSocket, WebRtcSocketBuilder,
+    error::{ChannelError, SendError, SignalingError},
+    BuildablePlurality, ChannelConfig, ChannelPlurality, ChannelStats, ConnectionFailure,
+    IceCandidateType, LinkConditions, MaybeSend, MaybeSync, MessageLoopFuture, MultipleChannels,
+    NetworkConditioner, NoChannels, Packet, PeerCandidate, PeerState, PeerStats,
+    RtcIceServerConfig, RtcIceTransportPolicy, SignalingReconnectPolicy, Signaller,
+    SignalingEvent, SignallerBuilder, SingleChannel, WebRtcChannel, WebRtcSocket,
+    WebRtcSocketBuilder,
 };
@@ -0,0 +1,202 @@
+use crate::{ChannelPlurality, Packet, SendError, WebRtcChannel, WebRtcSocket};
+use log::error;
+use matchbox_protocol::PeerId;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{error::Error as StdError, marker::PhantomData};
+
+/// An error that occurred while encoding a message into a [`Packet`]
+#[derive(Debug, thiserror::Error)]
+#[error("failed to encode message: {0}")]
+pub struct EncodeError(#[source] Box<dyn StdError + Send + Sync>);
+
+impl EncodeError {
+    /// Wraps the error of a [`Codec`]
+    pub fn new(source: impl Into<Box<dyn StdError + Send + Sync>>) -> Self {
+        Self(source.into())
+    }
+}
+
+/// An error that occurred while decoding a received [`Packet`], e.g. because it was malformed
+#[derive(Debug, thiserror::Error)]
+#[error("failed to decode message: {0}")]
+pub struct DecodeError(#[source] Box<dyn StdError + Send + Sync>);
+
+impl DecodeError {
+    /// Wraps the error of a [`Codec`]
+    pub fn new(source: impl Into<Box<dyn StdError + Send + Sync>>) -> Self {
+        Self(source.into())
+    }
+}
+
+/// An error that can occur when sending a message on a [`TypedChannel`]
+#[derive(Debug, thiserror::Error)]
+pub enum TypedSendError {
+    /// The message could not be encoded
+    #[error(transparent)]
+    Encode(#[from] EncodeError),
+    /// The encoded packet could not be sent
+    #[error(transparent)]
+    Send(#[from] SendError),
+}
+
+/// Converts messages to and from the [`Packet`]s sent over a [`TypedChannel`]
+pub trait Codec {
+    /// Encodes a message into a packet
+    fn encode<T: Serialize>(message: &T) -> Result<Packet, EncodeError>;
+
+    /// Decodes a message from a received packet
+    fn decode<T: DeserializeOwned>(packet: &[u8]) -> Result<T, DecodeError>;
+}
+
+/// Encodes messages as JSON, which is easy to debug but not very compact
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Json;
+
+impl Codec for Json {
+    fn encode<T: Serialize>(message: &T) -> Result<Packet, EncodeError> {
+        serde_json::to_vec(message)
+            .map(Vec::into_boxed_slice)
+            .map_err(EncodeError::new)
+    }
+
+    fn decode<T: DeserializeOwned>(packet: &[u8]) -> Result<T, DecodeError> {
+        serde_json::from_slice(packet).map_err(DecodeError::new)
+    }
+}
+
+/// Encodes messages with [`bincode`], a compact binary format
+#[cfg(feature = "bincode")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Bincode;
+
+#[cfg(feature = "bincode")]
+impl Codec for Bincode {
+    fn encode<T: Serialize>(message: &T) -> Result<Packet, EncodeError> {
+        bincode::serialize(message)
+            .map(Vec::into_boxed_slice)
+            .map_err(EncodeError::new)
+    }
+
+    fn decode<T: DeserializeOwned>(packet: &[u8]) -> Result<T, DecodeError> {
+        bincode::deserialize(packet).map_err(DecodeError::new)
+    }
+}
+
+/// A [`WebRtcChannel`] that sends and receives messages of type `T` instead of raw [`Packet`]s,
+/// using the [`Codec`] `C`.
+///
+/// ```
+/// use matchbox_socket::*;
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Serialize, Deserialize)]
+/// enum ChatMessage {
+///     Hello { name: String },
+/// }
+///
+/// let (mut socket, message_loop) = WebRtcSocketBuilder::new("wss://example.invalid/")
+///     .add_channel(ChannelConfig::reliable())
+///     .build();
+/// let mut chat = socket.take_typed_channel::<ChatMessage, Json>(0).unwrap();
+/// for (peer, message) in chat.receive() {
+///     match message {
+///         Ok(ChatMessage::Hello { name }) => println!("{peer} is called {name}"),
+///         Err(err) => println!("{peer} sent a malformed message: {err}"),
+///     }
+/// }
+/// ```
+#[derive(Debug)]
+pub struct TypedChannel<T, C: Codec = Json> {
+    channel: WebRtcChannel,
+    marker: PhantomData<fn(T, C) -> T>,
+}
+
+impl<T, C: Codec> From<WebRtcChannel> for TypedChannel<T, C> {
+    fn from(channel: WebRtcChannel) -> Self {
+        Self {
+            channel,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T: Serialize + DeserializeOwned, C: Codec> TypedChannel<T, C> {
+    /// Call this where you want to handle new received messages. Returns immediately.
+    ///
+    /// Messages are removed from the socket when called. Packets that can't be decoded are
+    /// returned as a [`DecodeError`].
+    pub fn receive(&mut self) -> Vec<(PeerId, Result<T, DecodeError>)> {
+        self.channel
+            .receive()
+            .into_iter()
+            .map(|(peer, packet)| (peer, C::decode(&packet)))
+            .collect()
+    }
+
+    /// Try to send a message to the given peer. An error is propagated if the message can't be
+    /// encoded, or if it can't be sent, see [`WebRtcChannel::try_send`]. `Ok` is not a
+    /// guarantee of delivery.
+    pub fn try_send(&mut self, message: &T, peer: PeerId) -> Result<(), TypedSendError> {
+        let packet = C::encode(message)?;
+        self.channel.try_send(packet, peer)?;
+        Ok(())
+    }
+
+    /// Send a message to the given peer. There is no guarantee of delivery.
+    ///
+    /// Messages that can't be encoded are logged and dropped, see [`WebRtcChannel::send`] for
+    /// how the encoded packet is sent.
+    ///
+    /// # Panics
+    /// Panics if the socket future is dropped.
+    pub fn send(&mut self, message: &T, peer: PeerId) {
+        match C::encode(message) {
+            Ok(packet) => self.channel.send(packet, peer),
+            Err(err) => error!("dropping message to {peer}: {err}"),
+        }
+    }
+
+    /// Returns the underlying [`WebRtcChannel`]
+    pub fn into_inner(self) -> WebRtcChannel {
+        self.channel
+    }
+}
+
+impl<C: ChannelPlurality> WebRtcSocket<C> {
+    /// Takes the [`WebRtcChannel`] of a given id as a [`TypedChannel`].
+    ///
+    /// See also: [`WebRtcSocket::take_channel`]
+    pub fn take_typed_channel<T: Serialize + DeserializeOwned, Co: Codec>(
+        &mut self,
+        channel: usize,
+    ) -> Result<TypedChannel<T, Co>, crate::ChannelError> {
+        self.take_channel(channel).map(TypedChannel::from)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Codec, Json};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Message {
+        frame: u32,
+        inputs: Vec<u8>,
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let message = Message {
+            frame: 7,
+            inputs: vec![1, 2, 3],
+        };
+        let packet = Json::encode(&message).unwrap();
+        assert_eq!(Json::decode::<Message>(&packet).unwrap(), message);
+    }
+
+    #[test]
+    fn malformed_packet_is_an_error() {
+        assert!(Json::decode::<Message>(b"\x00garbage").is_err());
+    }
+}
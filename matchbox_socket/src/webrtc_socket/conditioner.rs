@@ -0,0 +1,321 @@
+use super::send_queue::QueuedPacket;
+use futures::{stream::FusedStream, FutureExt, Stream, StreamExt};
+use futures_timer::Delay;
+use matchbox_protocol::PeerId;
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+/// Simulated network conditions for outgoing packets, see [`NetworkConditioner`].
+///
+/// The default is a perfect link, which delivers every packet once and without delay.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LinkConditions {
+    /// Delay added to every packet
+    pub latency: Duration,
+    /// Maximum random deviation from the latency, in either direction
+    pub jitter: Duration,
+    /// Probability between 0 and 1 that a packet is dropped
+    pub loss: f64,
+    /// Probability between 0 and 1 that a packet is sent twice
+    pub duplication: f64,
+    /// Probability between 0 and 1 that a packet skips the latency, overtaking packets sent
+    /// before it. Has no effect without latency.
+    pub reordering: f64,
+}
+
+/// Simulates bad network conditions on the packets sent by a socket, to test how a game copes
+/// with them.
+///
+/// The conditioner is a handle, its conditions can be changed at any time, e.g. once the id of
+/// a peer is known. Conditions set for a peer take precedence over those set for a channel,
+/// which take precedence over the default conditions.
+///
+/// Random decisions are made with a generator seeded by [`NetworkConditioner::new`], so the same
+/// packets sent in the same order are affected the same way. Packets on reliable channels are
+/// only delayed by the latency, as they are never lost, duplicated or reordered.
+///
+/// ```
+/// use matchbox_socket::*;
+/// use std::time::Duration;
+///
+/// let conditioner = NetworkConditioner::new(42);
+/// conditioner.set_conditions(LinkConditions {
+///     latency: Duration::from_millis(100),
+///     jitter: Duration::from_millis(20),
+///     loss: 0.05,
+///     ..Default::default()
+/// });
+/// let (socket, message_loop) = WebRtcSocketBuilder::new("wss://example.invalid/")
+///     .network_conditioner(&conditioner)
+///     .add_channel(ChannelConfig::unreliable())
+///     .build();
+/// ```
+#[derive(Debug, Clone)]
+pub struct NetworkConditioner(Arc<Mutex<ConditionerState>>);
+
+#[derive(Debug)]
+struct ConditionerState {
+    rng: SplitMix64,
+    default: LinkConditions,
+    channels: HashMap<usize, LinkConditions>,
+    peers: HashMap<PeerId, LinkConditions>,
+}
+
+impl NetworkConditioner {
+    /// Creates a conditioner with a perfect link and the given random seed
+    pub fn new(seed: u64) -> Self {
+        Self(Arc::new(Mutex::new(ConditionerState {
+            rng: SplitMix64(seed),
+            default: LinkConditions::default(),
+            channels: HashMap::new(),
+            peers: HashMap::new(),
+        })))
+    }
+
+    /// Sets the conditions for all channels and peers without conditions of their own
+    pub fn set_conditions(&self, conditions: LinkConditions) {
+        self.0.lock().unwrap().default = conditions;
+    }
+
+    /// Sets the conditions for the channel with the given index, `None` to use the default
+    pub fn set_channel_conditions(&self, channel: usize, conditions: Option<LinkConditions>) {
+        let channels = &mut self.0.lock().unwrap().channels;
+        match conditions {
+            Some(conditions) => channels.insert(channel, conditions),
+            None => channels.remove(&channel),
+        };
+    }
+
+    /// Sets the conditions for packets sent to the given peer on any channel, `None` to use the
+    /// channel or default conditions
+    pub fn set_peer_conditions(&self, peer: PeerId, conditions: Option<LinkConditions>) {
+        let peers = &mut self.0.lock().unwrap().peers;
+        match conditions {
+            Some(conditions) => peers.insert(peer, conditions),
+            None => peers.remove(&peer),
+        };
+    }
+
+    /// Decides the fate of a packet, returning the delay of each copy that should be sent.
+    pub(crate) fn delays(&self, channel: usize, reliable: bool, peer: PeerId) -> Vec<Duration> {
+        let mut state = self.0.lock().unwrap();
+        let conditions = *state
+            .peers
+            .get(&peer)
+            .or_else(|| state.channels.get(&channel))
+            .unwrap_or(&state.default);
+        if reliable {
+            return vec![conditions.latency];
+        }
+
+        let rng = &mut state.rng;
+        if rng.chance(conditions.loss) {
+            return vec![];
+        }
+        let copies = if rng.chance(conditions.duplication) {
+            2
+        } else {
+            1
+        };
+        (0..copies)
+            .map(|_| {
+                if rng.chance(conditions.reordering) {
+                    Duration::ZERO
+                } else {
+                    let deviation = conditions.jitter.mul_f64(rng.next_f64() * 2.0);
+                    (conditions.latency + deviation).saturating_sub(conditions.jitter)
+                }
+            })
+            .collect()
+    }
+}
+
+/// A small, fast generator, so that conditioning is reproducible on every platform.
+#[derive(Debug)]
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a number in `[0, 1)`
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        probability > 0.0 && self.next_f64() < probability
+    }
+}
+
+struct DelayedPacket {
+    delay: Delay,
+    channel: usize,
+    peer: PeerId,
+    packet: Option<QueuedPacket>,
+}
+
+impl Future for DelayedPacket {
+    type Output = (usize, PeerId, QueuedPacket);
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        futures::ready!(self.delay.poll_unpin(cx));
+        let packet = self
+            .packet
+            .take()
+            .expect("delayed packet polled after completion");
+        Poll::Ready((self.channel, self.peer, packet))
+    }
+}
+
+/// Packets held back by a [`NetworkConditioner`], yielded once their delay has passed.
+///
+/// Packets on the same reliable channel to the same peer are yielded in the order they were
+/// pushed, the rest as soon as their delay has passed.
+#[derive(Default)]
+pub(crate) struct DelayedPackets {
+    unordered: futures::stream::FuturesUnordered<DelayedPacket>,
+    ordered: HashMap<(usize, PeerId), VecDeque<DelayedPacket>>,
+}
+
+impl DelayedPackets {
+    /// Holds back a packet, or returns it if it can be sent right away.
+    pub(crate) fn push(
+        &mut self,
+        channel: usize,
+        peer: PeerId,
+        packet: QueuedPacket,
+        delay: Duration,
+        ordered: bool,
+    ) -> Option<QueuedPacket> {
+        let waiting = self
+            .ordered
+            .get(&(channel, peer))
+            .is_some_and(|queue| !queue.is_empty());
+        if delay.is_zero() && !waiting {
+            return Some(packet);
+        }
+        let delayed = DelayedPacket {
+            delay: Delay::new(delay),
+            channel,
+            peer,
+            packet: Some(packet),
+        };
+        if ordered {
+            self.ordered
+                .entry((channel, peer))
+                .or_default()
+                .push_back(delayed);
+        } else {
+            self.unordered.push(delayed);
+        }
+        None
+    }
+}
+
+impl Stream for DelayedPackets {
+    type Item = (usize, PeerId, QueuedPacket);
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if let Poll::Ready(Some(item)) = self.unordered.poll_next_unpin(cx) {
+            return Poll::Ready(Some(item));
+        }
+        self.ordered.retain(|_, queue| !queue.is_empty());
+        for queue in self.ordered.values_mut() {
+            if let Some(Poll::Ready(item)) = queue.front_mut().map(|head| head.poll_unpin(cx)) {
+                queue.pop_front();
+                return Poll::Ready(Some(item));
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl FusedStream for DelayedPackets {
+    fn is_terminated(&self) -> bool {
+        // Nothing to wait for until the next packet is pushed
+        self.unordered.is_empty() && self.ordered.values().all(VecDeque::is_empty)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{LinkConditions, NetworkConditioner};
+    use matchbox_protocol::PeerId;
+    use std::time::Duration;
+
+    const PEER: PeerId = PeerId(uuid::Uuid::from_u128(1));
+
+    fn lossy() -> LinkConditions {
+        LinkConditions {
+            latency: Duration::from_millis(100),
+            jitter: Duration::from_millis(20),
+            loss: 0.2,
+            duplication: 0.1,
+            reordering: 0.1,
+        }
+    }
+
+    #[test]
+    fn same_seed_gives_same_delays() {
+        let delays = |seed| {
+            let conditioner = NetworkConditioner::new(seed);
+            conditioner.set_conditions(lossy());
+            (0..100)
+                .map(|_| conditioner.delays(0, false, PEER))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(delays(7), delays(7));
+        assert_ne!(delays(7), delays(8));
+
+        let delays = delays(7);
+        assert!(delays.iter().any(Vec::is_empty));
+        assert!(delays.iter().any(|copies| copies.len() == 2));
+        assert!(delays.iter().flatten().any(Duration::is_zero));
+        assert!(delays.iter().flatten().all(|delay| delay.is_zero()
+            || (Duration::from_millis(80)..=Duration::from_millis(120)).contains(delay)));
+    }
+
+    #[test]
+    fn reliable_channels_only_get_latency() {
+        let conditioner = NetworkConditioner::new(0);
+        conditioner.set_conditions(lossy());
+        for _ in 0..100 {
+            assert_eq!(
+                conditioner.delays(0, true, PEER),
+                vec![Duration::from_millis(100)]
+            );
+        }
+    }
+
+    #[test]
+    fn peer_conditions_override_channel_conditions() {
+        let conditioner = NetworkConditioner::new(0);
+        let latency = |millis| LinkConditions {
+            latency: Duration::from_millis(millis),
+            ..Default::default()
+        };
+        conditioner.set_conditions(latency(1));
+        conditioner.set_channel_conditions(1, Some(latency(2)));
+        assert_eq!(conditioner.delays(0, false, PEER), [latency(1).latency]);
+        assert_eq!(conditioner.delays(1, false, PEER), [latency(2).latency]);
+
+        conditioner.set_peer_conditions(PEER, Some(latency(3)));
+        assert_eq!(conditioner.delays(1, false, PEER), [latency(3).latency]);
+
+        conditioner.set_peer_conditions(PEER, None);
+        conditioner.set_channel_conditions(1, None);
+        assert_eq!(conditioner.delays(1, false, PEER), [latency(1).latency]);
+    }
+}
@@ -1,4 +1,4 @@
-use crate::webrtc_socket::messages::SignalEvent;
+use crate::webrtc_socket::messages::SignalingEvent;
 use cfg_if::cfg_if;
 
 /// An error that can occur when getting a socket's channel through
@@ -19,28 +19,71 @@ pub enum ChannelError {
     Closed,
 }
 
+/// An error that can occur when sending a packet on a [`WebRtcChannel`](crate::WebRtcChannel).
+#[derive(Debug, thiserror::Error)]
+pub enum SendError {
+    /// The channel's send queue for this peer is full, see
+    /// [`ChannelConfig::send_queue_capacity`](crate::ChannelConfig::send_queue_capacity)
+    #[error("The send queue for this peer is full")]
+    WouldBlock,
+    /// The socket future was dropped, so the packet can never be sent
+    #[error("The socket was dropped and the packet could not be sent")]
+    Closed,
+}
+
 /// An error that can occur with WebRTC messaging.
 #[derive(Debug, thiserror::Error)]
 pub enum SignalingError {
     // Common
+    /// The socket was dropped, so signaling events can't be delivered anymore
     #[error("failed to send to signaling server: {0}")]
-    UndeliverableSignal(#[from] futures_channel::mpsc::TrySendError<SignalEvent>),
+    UndeliverableSignal(#[from] futures_channel::mpsc::TrySendError<SignalingEvent>),
 
+    /// The connection to the signaling server was closed
     #[error("The stream is exhausted")]
     StreamExhausted,
 
+    /// A message from the signaling server was not in the expected format
     #[error("Message received in unknown format")]
     UnknownFormat,
 
+    /// A message from the signaling server could not be parsed as a [`SignalEvent`], e.g.
+    /// because the server uses a newer version of the protocol.
+    ///
+    /// Such messages are logged and ignored, unless
+    /// [`WebRtcSocketBuilder::strict_signaling`](crate::WebRtcSocketBuilder::strict_signaling)
+    /// is enabled.
+    #[error("malformed message from signaling server: {source}. Message: {message}")]
+    MalformedMessage {
+        /// The message as received
+        message: String,
+        /// Why it couldn't be parsed
+        #[source]
+        source: serde_json::Error,
+    },
+
+    /// No connection to the signaling server could be established
     #[error("failed to establish initial connection: {0}")]
     NegotiationFailed(#[from] Box<SignalingError>),
 
+    /// An error from a custom [`Signaller`](crate::Signaller) implementation
+    #[error("signaller failure: {0}")]
+    Custom(Box<dyn std::error::Error + Send + Sync>),
+
     // Native
+    /// The websocket connection to the signaling server failed
     #[cfg(not(target_arch = "wasm32"))]
     #[error("socket failure communicating with signaling server: {0}")]
     WebSocket(#[from] async_tungstenite::tungstenite::Error),
 
+    /// The TLS configuration for the signaling server is invalid, e.g. a certificate couldn't be
+    /// parsed
+    #[cfg(not(target_arch = "wasm32"))]
+    #[error("invalid signaling TLS configuration: {0}")]
+    TlsConfig(Box<dyn std::error::Error + Send + Sync>),
+
     // WASM
+    /// The websocket connection to the signaling server failed
     #[cfg(target_arch = "wasm32")]
     #[error("socket failure communicating with signaling server: {0}")]
     WebSocket(#[from] ws_stream_wasm::WsErr),
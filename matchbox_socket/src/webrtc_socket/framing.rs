@@ -0,0 +1,137 @@
+//! Fragmentation of large packets on reliable channels.
+//!
+//! On channels with a [`ChannelConfig::fragment_size`](crate::ChannelConfig::fragment_size),
+//! every message on the data channel starts with a header byte telling whether more fragments of
+//! the same packet follow. As these channels are reliable and ordered, the receiver only has to
+//! concatenate fragments until it sees the last one.
+
+use crate::webrtc_socket::Packet;
+use log::warn;
+
+const MORE_FRAGMENTS: u8 = 0;
+const LAST_FRAGMENT: u8 = 1;
+
+/// Splits a packet into framed fragments carrying at most `fragment_size` bytes of payload each.
+pub(crate) fn fragment(packet: &[u8], fragment_size: usize) -> Vec<Packet> {
+    let mut chunks: Vec<&[u8]> = packet.chunks(fragment_size.max(1)).collect();
+    if chunks.is_empty() {
+        // Empty packets still need a frame to arrive at all
+        chunks.push(&[]);
+    }
+
+    let last = chunks.len() - 1;
+    chunks
+        .into_iter()
+        .enumerate()
+        .map(|(i, chunk)| {
+            let mut frame = Vec::with_capacity(chunk.len() + 1);
+            frame.push(if i == last {
+                LAST_FRAGMENT
+            } else {
+                MORE_FRAGMENTS
+            });
+            frame.extend_from_slice(chunk);
+            frame.into_boxed_slice()
+        })
+        .collect()
+}
+
+/// Reassembles the fragments of packets received on a single channel from a single peer
+#[derive(Debug)]
+pub(crate) struct Reassembler {
+    buffer: Vec<u8>,
+    /// Packets growing larger than this many bytes are discarded
+    max_size: usize,
+    /// Whether the remaining fragments of the current packet are discarded
+    oversized: bool,
+}
+
+impl Reassembler {
+    pub(crate) fn new(max_size: usize) -> Self {
+        Self {
+            buffer: Vec::new(),
+            max_size,
+            oversized: false,
+        }
+    }
+
+    /// Adds a received frame, returning the packet once all of its fragments have arrived
+    pub(crate) fn push(&mut self, frame: &[u8]) -> Option<Packet> {
+        let Some((&header, payload)) = frame.split_first() else {
+            warn!("discarding empty frame");
+            return None;
+        };
+
+        match header {
+            MORE_FRAGMENTS | LAST_FRAGMENT => {
+                if !self.oversized && self.buffer.len() + payload.len() > self.max_size {
+                    warn!("discarding packet larger than {} bytes", self.max_size);
+                    // Free the memory right away, the packet may be far from complete
+                    self.buffer = Vec::new();
+                    self.oversized = true;
+                }
+                if !self.oversized {
+                    self.buffer.extend_from_slice(payload);
+                }
+                if header == MORE_FRAGMENTS || std::mem::take(&mut self.oversized) {
+                    return None;
+                }
+                Some(std::mem::take(&mut self.buffer).into_boxed_slice())
+            }
+            _ => {
+                warn!("discarding packet with invalid fragment header {header}, is fragmentation enabled on both ends?");
+                self.buffer.clear();
+                self.oversized = false;
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{fragment, Reassembler};
+
+    #[test]
+    fn fragments_are_reassembled() {
+        let packet: Vec<u8> = (0..=255).collect();
+        let fragments = fragment(&packet, 100);
+        assert_eq!(fragments.len(), 3);
+        assert!(fragments.iter().all(|fragment| fragment.len() <= 101));
+
+        let mut reassembler = Reassembler::new(1024);
+        assert_eq!(reassembler.push(&fragments[0]), None);
+        assert_eq!(reassembler.push(&fragments[1]), None);
+        assert_eq!(
+            reassembler.push(&fragments[2]).as_deref(),
+            Some(&packet[..])
+        );
+    }
+
+    #[test]
+    fn small_and_empty_packets_are_single_frames() {
+        let mut reassembler = Reassembler::new(1024);
+        for packet in [&b""[..], &b"hi"[..]] {
+            let fragments = fragment(packet, 100);
+            assert_eq!(fragments.len(), 1);
+            assert_eq!(reassembler.push(&fragments[0]).as_deref(), Some(packet));
+        }
+    }
+
+    #[test]
+    fn oversized_packets_are_discarded() {
+        let mut reassembler = Reassembler::new(150);
+        let oversized: Vec<u8> = (0..=255).collect();
+        for frame in fragment(&oversized, 100) {
+            assert_eq!(reassembler.push(&frame), None);
+        }
+
+        // The next packet is unaffected
+        let fragments = fragment(&oversized[..150], 100);
+        assert_eq!(reassembler.push(&fragments[0]), None);
+        assert_eq!(
+            reassembler.push(&fragments[1]).as_deref(),
+            Some(&oversized[..150])
+        );
+    }
+}
@@ -0,0 +1,718 @@
+//! An in-memory backend connecting sockets in the same process, for testing without a signaling
+//! server or WebRTC.
+//!
+//! [`LoopbackNetwork`] plays the part of a full-mesh signaling server, and [`LoopbackMessenger`]
+//! replaces the WebRTC peer connections with channels. The handshake still goes through the
+//! signaling messages: the offering peer parks its end of the connection on the network and sends
+//! the key as its offer, which the answering peer uses to claim it.
+
+use super::{send_queue::QueuedPacket, HandshakeResult, PacketSendError, PeerDataSender};
+use crate::{
+    webrtc_socket::{
+        error::SignalingError,
+        framing::Reassembler,
+        messages::{PeerRequest, PeerSignal, SignalEvent},
+        signal_peer::SignalPeer,
+        ChannelConfig, IceConfig, Messenger, Packet, PeerStats, Signaller, SignallerBuilder,
+    },
+    ChannelPlurality, WebRtcSocketBuilder,
+};
+use async_trait::async_trait;
+use futures::{
+    future::{self, Either},
+    stream::FuturesUnordered,
+    StreamExt,
+};
+use futures_channel::{
+    mpsc::{Receiver, Sender, UnboundedReceiver, UnboundedSender},
+    oneshot,
+};
+use log::{debug, trace, warn};
+use matchbox_protocol::{PeerEvent, PeerId};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use uuid::Uuid;
+
+/// How many packets can be in flight on an unreliable channel before new ones are dropped
+const UNRELIABLE_BUFFER_SIZE: usize = 64;
+
+/// The event senders of the peers in a room
+type Room = HashMap<PeerId, UnboundedSender<String>>;
+
+/// An in-memory network connecting [`WebRtcSocket`](crate::WebRtcSocket)s in the same process.
+///
+/// Sockets built with [`WebRtcSocketBuilder::loopback`] join the room given by their room url on
+/// this network, and connect to each other in a full mesh just like through a matchbox server.
+/// Packets are passed through channels, so no signaling server or WebRTC is involved.
+///
+/// Reliable channels deliver every packet in order. Unreliable channels drop packets if the
+/// receiving socket falls more than a few dozen packets behind, like a congested connection
+/// would. Networks are independent of each other, so tests can run in parallel.
+///
+/// ```
+/// use matchbox_socket::*;
+///
+/// let network = LoopbackNetwork::default();
+/// let (mut host, host_loop) = WebRtcSocketBuilder::new("room")
+///     .loopback(&network)
+///     .add_channel(ChannelConfig::reliable())
+///     .build();
+/// let (mut client, client_loop) = WebRtcSocketBuilder::new("room")
+///     .loopback(&network)
+///     .add_channel(ChannelConfig::reliable())
+///     .build();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct LoopbackNetwork {
+    rooms: Arc<Mutex<HashMap<String, Room>>>,
+    next_peer_id: Arc<AtomicU64>,
+    pending_connections: Arc<Mutex<PendingConnections>>,
+}
+
+/// Connections offered to a peer that hasn't answered yet, by the key sent in the offer
+#[derive(Default)]
+struct PendingConnections {
+    next_key: u64,
+    parked: BTreeMap<u64, LoopbackConnection>,
+}
+
+impl std::fmt::Debug for PendingConnections {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PendingConnections")
+            .field("next_key", &self.next_key)
+            .field("parked", &self.parked.keys())
+            .finish()
+    }
+}
+
+impl LoopbackNetwork {
+    fn join(&self, room: String) -> LoopbackSignaller {
+        let id = self.next_peer_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let id = PeerId(Uuid::from_u128(id.into()));
+        let (events_tx, events_rx) = futures_channel::mpsc::unbounded();
+
+        let mut rooms = self.rooms.lock().unwrap();
+        let peers = rooms.entry(room.clone()).or_default();
+        // Sessions can't be resumed on a loopback network, so no resume token is handed out
+        send_event(&events_tx, PeerEvent::IdAssigned(id));
+        for peer in peers.values() {
+            send_event(peer, PeerEvent::NewPeer(id));
+        }
+        peers.insert(id, events_tx);
+        debug!("{id} joined loopback room {room:?}");
+
+        LoopbackSignaller {
+            id,
+            room,
+            network: self.clone(),
+            events_rx,
+        }
+    }
+
+    fn send_to(&self, room: &str, receiver: PeerId, event: PeerEvent<PeerSignal>) {
+        let rooms = self.rooms.lock().unwrap();
+        match rooms.get(room).and_then(|peers| peers.get(&receiver)) {
+            Some(peer) => send_event(peer, event),
+            None => warn!("dropping signal for {receiver}, who isn't in loopback room {room:?}"),
+        }
+    }
+
+    fn leave(&self, room: &str, id: PeerId) {
+        let mut rooms = self.rooms.lock().unwrap();
+        let Some(peers) = rooms.get_mut(room) else {
+            return;
+        };
+        if peers.remove(&id).is_none() {
+            // Already left
+            return;
+        }
+        for peer in peers.values() {
+            send_event(peer, PeerEvent::PeerLeft(id));
+        }
+        if peers.is_empty() {
+            rooms.remove(room);
+        }
+        debug!("{id} left loopback room {room:?}");
+    }
+
+    /// Parks one end of a connection until the peer claims it with the returned key
+    fn park(&self, connection: LoopbackConnection) -> PendingConnection {
+        let mut pending = self.pending_connections.lock().unwrap();
+        let key = pending.next_key;
+        pending.next_key += 1;
+        pending.parked.insert(key, connection);
+        PendingConnection {
+            key,
+            network: self.clone(),
+        }
+    }
+
+    fn claim(&self, key: u64) -> Option<LoopbackConnection> {
+        let mut pending = self.pending_connections.lock().unwrap();
+        pending.parked.remove(&key)
+    }
+}
+
+fn send_event(peer: &UnboundedSender<String>, event: PeerEvent<PeerSignal>) {
+    let event = serde_json::to_string(&SignalEvent::Peer(event)).expect("serializing event");
+    // The peer's signaller is being dropped, and will leave the room shortly
+    let _ = peer.unbounded_send(event);
+}
+
+#[async_trait]
+impl SignallerBuilder for LoopbackNetwork {
+    async fn new_signaller(
+        &self,
+        _attempts: Option<u16>,
+        room_url: String,
+    ) -> Result<Box<dyn Signaller>, SignalingError> {
+        // Ignore query parameters, e.g. the resume token added when reconnecting
+        let room = match room_url.split_once('?') {
+            Some((room, _)) => room.to_string(),
+            None => room_url,
+        };
+        Ok(Box::new(self.join(room)))
+    }
+}
+
+struct LoopbackSignaller {
+    id: PeerId,
+    room: String,
+    network: LoopbackNetwork,
+    events_rx: UnboundedReceiver<String>,
+}
+
+#[async_trait]
+impl Signaller for LoopbackSignaller {
+    async fn send(&mut self, request: String) -> Result<(), SignalingError> {
+        let request: PeerRequest =
+            serde_json::from_str(&request).map_err(|err| SignalingError::Custom(err.into()))?;
+        match request {
+            PeerRequest::Signal { receiver, data } => {
+                let event = PeerEvent::Signal {
+                    sender: self.id,
+                    data,
+                };
+                self.network.send_to(&self.room, receiver, event);
+            }
+            PeerRequest::KeepAlive => {}
+            PeerRequest::Leave => self.network.leave(&self.room, self.id),
+        }
+        Ok(())
+    }
+
+    async fn next_message(&mut self) -> Result<String, SignalingError> {
+        self.events_rx
+            .next()
+            .await
+            .ok_or(SignalingError::StreamExhausted)
+    }
+}
+
+impl Drop for LoopbackSignaller {
+    fn drop(&mut self) {
+        self.network.leave(&self.room, self.id);
+    }
+}
+
+impl<C: ChannelPlurality> WebRtcSocketBuilder<C> {
+    /// Connects the socket to other sockets on an in-memory [`LoopbackNetwork`], instead of
+    /// using the signaling server and WebRTC.
+    ///
+    /// The room url only identifies the room on the network. ICE servers are ignored and no
+    /// peer statistics are collected.
+    pub fn loopback(mut self, network: &LoopbackNetwork) -> Self {
+        self.config.signaller_builder = Some(Arc::new(network.clone()));
+        self.config.loopback = Some(network.clone());
+        self
+    }
+}
+
+pub(crate) struct LoopbackDataChannel {
+    tx: Either<UnboundedSender<Packet>, Sender<Packet>>,
+    /// Shared by the data channels to a peer, the peer loop finishes once all of them are dropped
+    _open: Arc<oneshot::Sender<()>>,
+}
+
+impl PeerDataSender for LoopbackDataChannel {
+    fn send(&mut self, packet: QueuedPacket) -> Result<(), PacketSendError> {
+        let data = Packet::from(&packet.packet[..]);
+        match &mut self.tx {
+            Either::Left(tx) => tx.unbounded_send(data).map_err(|err| PacketSendError {
+                source: err.into_send_error(),
+            }),
+            Either::Right(tx) => match tx.try_send(data) {
+                Err(err) if err.is_full() => {
+                    trace!("loopback channel is congested, dropping packet");
+                    Ok(())
+                }
+                result => result.map_err(|err| PacketSendError {
+                    source: err.into_send_error(),
+                }),
+            },
+        }
+    }
+}
+
+pub(crate) struct LoopbackIncoming {
+    rx: Either<UnboundedReceiver<Packet>, Receiver<Packet>>,
+    reassembler: Option<Reassembler>,
+}
+
+/// One end of a connection between two peers
+struct LoopbackConnection {
+    data_channels: Vec<LoopbackDataChannel>,
+    incoming: Vec<LoopbackIncoming>,
+    /// Completes once this end's data channels are dropped
+    closed: oneshot::Receiver<()>,
+}
+
+impl LoopbackConnection {
+    /// Creates both ends of a connection with the given channels
+    fn pair(channel_configs: &[ChannelConfig]) -> (Self, Self) {
+        let (a_open, a_closed) = oneshot::channel();
+        let (b_open, b_closed) = oneshot::channel();
+        let (a_open, b_open) = (Arc::new(a_open), Arc::new(b_open));
+        let mut ends = (Self::empty(a_closed), Self::empty(b_closed));
+        for config in channel_configs {
+            let (a_tx, a_rx) = direction(config);
+            let (b_tx, b_rx) = direction(config);
+            ends.0.data_channels.push(LoopbackDataChannel {
+                tx: a_tx,
+                _open: Arc::clone(&a_open),
+            });
+            ends.1.incoming.push(a_rx);
+            ends.1.data_channels.push(LoopbackDataChannel {
+                tx: b_tx,
+                _open: Arc::clone(&b_open),
+            });
+            ends.0.incoming.push(b_rx);
+        }
+        ends
+    }
+
+    fn empty(closed: oneshot::Receiver<()>) -> Self {
+        Self {
+            data_channels: vec![],
+            incoming: vec![],
+            closed,
+        }
+    }
+}
+
+/// Creates a channel carrying packets in one direction
+fn direction(
+    config: &ChannelConfig,
+) -> (
+    Either<UnboundedSender<Packet>, Sender<Packet>>,
+    LoopbackIncoming,
+) {
+    let (tx, rx) = if config.is_reliable() {
+        let (tx, rx) = futures_channel::mpsc::unbounded();
+        (Either::Left(tx), Either::Left(rx))
+    } else {
+        let (tx, rx) = futures_channel::mpsc::channel(UNRELIABLE_BUFFER_SIZE);
+        (Either::Right(tx), Either::Right(rx))
+    };
+    let reassembler = config.reassembler();
+    (tx, LoopbackIncoming { rx, reassembler })
+}
+
+pub(crate) struct LoopbackMessenger {
+    network: LoopbackNetwork,
+}
+
+impl LoopbackMessenger {
+    pub(crate) fn new(network: LoopbackNetwork) -> Self {
+        Self { network }
+    }
+}
+
+#[async_trait]
+impl Messenger for LoopbackMessenger {
+    type DataChannel = LoopbackDataChannel;
+    type HandshakeMeta = (
+        Vec<LoopbackIncoming>,
+        Vec<UnboundedSender<(PeerId, Packet)>>,
+        oneshot::Receiver<()>,
+    );
+
+    async fn offer_handshake(
+        &self,
+        signal_peer: SignalPeer,
+        mut peer_signal_rx: UnboundedReceiver<PeerSignal>,
+        messages_from_peers_tx: Vec<UnboundedSender<(PeerId, Packet)>>,
+        _ice_config: &IceConfig,
+        channel_configs: &[ChannelConfig],
+    ) -> HandshakeResult<Self::DataChannel, Self::HandshakeMeta> {
+        let (ours, theirs) = LoopbackConnection::pair(channel_configs);
+        let pending = self.network.park(theirs);
+
+        debug!("making loopback offer");
+        signal_peer.send(PeerSignal::Offer(pending.key.to_string()));
+
+        loop {
+            let signal = peer_signal_rx
+                .next()
+                .await
+                .expect("Signal server connection lost in the middle of a handshake");
+
+            match signal {
+                PeerSignal::Answer(_) => break,
+                _ => warn!("Got an unexpected signal, while waiting for Answer. Ignoring."),
+            }
+        }
+        drop(pending);
+
+        HandshakeResult {
+            peer_id: signal_peer.id,
+            data_channels: ours.data_channels,
+            metadata: (ours.incoming, messages_from_peers_tx, ours.closed),
+        }
+    }
+
+    async fn accept_handshake(
+        &self,
+        signal_peer: SignalPeer,
+        mut peer_signal_rx: UnboundedReceiver<PeerSignal>,
+        messages_from_peers_tx: Vec<UnboundedSender<(PeerId, Packet)>>,
+        _ice_config: &IceConfig,
+        _channel_configs: &[ChannelConfig],
+    ) -> HandshakeResult<Self::DataChannel, Self::HandshakeMeta> {
+        let connection = loop {
+            let signal = peer_signal_rx
+                .next()
+                .await
+                .expect("Signal server connection lost in the middle of a handshake");
+
+            let PeerSignal::Offer(key) = signal else {
+                warn!("Got an unexpected signal, while waiting for Offer. Ignoring.");
+                continue;
+            };
+            let connection = key.parse().ok().and_then(|key| self.network.claim(key));
+            match connection {
+                Some(connection) => break connection,
+                None => warn!("Got an offer for an unknown loopback connection. Ignoring."),
+            }
+        };
+
+        debug!("answering loopback offer");
+        signal_peer.send(PeerSignal::Answer(String::new()));
+
+        HandshakeResult {
+            peer_id: signal_peer.id,
+            data_channels: connection.data_channels,
+            metadata: (
+                connection.incoming,
+                messages_from_peers_tx,
+                connection.closed,
+            ),
+        }
+    }
+
+    async fn peer_loop(
+        peer_uuid: PeerId,
+        handshake_meta: Self::HandshakeMeta,
+        _stats_interval: Option<Duration>,
+        _peer_stats_tx: UnboundedSender<(PeerId, PeerStats)>,
+    ) -> PeerId {
+        let (incoming, messages_from_peers_tx, closed) = handshake_meta;
+        let mut forwarders: FuturesUnordered<_> = incoming
+            .into_iter()
+            .zip(messages_from_peers_tx)
+            .map(|(mut incoming, tx)| async move {
+                while let Some(packet) = incoming.rx.next().await {
+                    let packet = match incoming.reassembler.as_mut() {
+                        Some(reassembler) => reassembler.push(&packet),
+                        None => Some(packet),
+                    };
+                    if let Some(packet) = packet {
+                        trace!("loopback message received: {packet:?}");
+                        if tx.unbounded_send((peer_uuid, packet)).is_err() {
+                            break;
+                        }
+                    }
+                }
+            })
+            .collect();
+
+        // The peer dropped its end of the connection, or we closed ours
+        future::select(forwarders.next(), closed).await;
+        peer_uuid
+    }
+}
+
+/// Removes a parked connection from the network if the peer never claims it
+struct PendingConnection {
+    key: u64,
+    network: LoopbackNetwork,
+}
+
+impl Drop for PendingConnection {
+    fn drop(&mut self) {
+        self.network.claim(self.key);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::LoopbackConnection;
+    use crate::{
+        ChannelConfig, LinkConditions, LoopbackNetwork, NetworkConditioner, Packet, PeerId,
+        PeerState, WebRtcSocketBuilder,
+    };
+    use futures::{future, FutureExt, StreamExt};
+    use futures_timer::Delay;
+    use serde_json::json;
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    #[futures_test::test]
+    async fn sockets_connect_and_exchange_packets() {
+        let network = LoopbackNetwork::default();
+        let (mut sockets, loops): (Vec<_>, Vec<_>) = (0..4)
+            .map(|_| {
+                WebRtcSocketBuilder::new("room")
+                    .loopback(&network)
+                    .add_channel(ChannelConfig::reliable().fragment_size(Some(2)))
+                    .build()
+            })
+            .unzip();
+        let mut loops = future::join_all(loops).fuse();
+
+        let test = async {
+            for socket in &mut sockets {
+                while socket.connected_peers().count() < 3 {
+                    socket.next_peer_event().await.unwrap();
+                }
+            }
+            let sender = sockets[0].id().unwrap();
+            assert!(sockets[0].broadcast(Box::new([1, 2, 3, 4, 5])).is_empty());
+            for socket in &mut sockets[1..] {
+                let expected: Packet = Box::new([1, 2, 3, 4, 5]);
+                assert_eq!(socket.channel(0).next().await, Some((sender, expected)));
+            }
+
+            drop(sockets.remove(0));
+            for socket in &mut sockets {
+                while socket.next_peer_event().await != Some((sender, PeerState::Disconnected)) {}
+            }
+        }
+        .fuse();
+
+        futures::select! {
+            _ = loops => panic!("message loops finished early"),
+            _ = Box::pin(test) => {}
+        }
+    }
+
+    #[test]
+    fn pending_connections_belong_to_their_network() {
+        let network = LoopbackNetwork::default();
+        let other = LoopbackNetwork::default();
+        let (_, theirs) = LoopbackConnection::pair(&[ChannelConfig::reliable()]);
+        let pending = network.park(theirs);
+
+        assert!(other.claim(pending.key).is_none());
+        assert!(network.claim(pending.key).is_some());
+        assert!(network.claim(pending.key).is_none());
+    }
+
+    #[futures_test::test]
+    async fn conditioner_delays_reliable_and_drops_unreliable_packets() {
+        let network = LoopbackNetwork::default();
+        let conditioner = NetworkConditioner::new(0);
+        conditioner.set_conditions(LinkConditions {
+            latency: Duration::from_millis(10),
+            jitter: Duration::from_millis(5),
+            loss: 1.0,
+            ..Default::default()
+        });
+        let (mut sender, sender_loop) = WebRtcSocketBuilder::new("room")
+            .loopback(&network)
+            .network_conditioner(&conditioner)
+            .add_channel(ChannelConfig::reliable())
+            .add_channel(ChannelConfig::unreliable())
+            .build();
+        let (mut receiver, receiver_loop) = WebRtcSocketBuilder::new("room")
+            .loopback(&network)
+            .add_channel(ChannelConfig::reliable())
+            .add_channel(ChannelConfig::unreliable())
+            .build();
+        let mut loops = future::join(sender_loop, receiver_loop).fuse();
+
+        let test = async {
+            let peer = loop {
+                if let Some((peer, PeerState::Connected)) = sender.next_peer_event().await {
+                    break peer;
+                }
+            };
+            for i in 0..20u8 {
+                sender.channel(1).send(Box::new([i]), peer);
+                sender.channel(0).send(Box::new([i]), peer);
+            }
+            for i in 0..20u8 {
+                let (_, packet) = receiver.channel(0).next().await.unwrap();
+                assert_eq!(*packet, [i]);
+            }
+            assert!(receiver.channel(1).receive().is_empty());
+        }
+        .fuse();
+
+        futures::select! {
+            _ = loops => panic!("message loops finished early"),
+            _ = Box::pin(test) => {}
+        }
+    }
+
+    #[futures_test::test]
+    async fn close_notifies_peers_and_finishes_the_loop() {
+        let network = LoopbackNetwork::default();
+        let build = || {
+            WebRtcSocketBuilder::new("room")
+                .loopback(&network)
+                .add_channel(ChannelConfig::reliable())
+                .build()
+        };
+        let (mut leaving, leaving_loop) = build();
+        let (mut staying, staying_loop) = build();
+        let mut leaving_loop = leaving_loop.fuse();
+        let mut staying_loop = staying_loop.fuse();
+
+        let connected = async {
+            loop {
+                if let Some((peer, PeerState::Connected)) = staying.next_peer_event().await {
+                    break peer;
+                }
+            }
+        };
+        let leaving_id = futures::select! {
+            _ = leaving_loop => panic!("message loop finished early"),
+            _ = staying_loop => panic!("message loop finished early"),
+            peer = Box::pin(connected.fuse()) => peer,
+        };
+
+        leaving.close();
+        let left = future::join(&mut leaving_loop, async {
+            while staying.next_peer_event().await != Some((leaving_id, PeerState::Disconnected)) {}
+        });
+        futures::select! {
+            _ = staying_loop => panic!("message loop finished early"),
+            (result, ()) = left.fuse() => assert!(result.is_ok()),
+        }
+    }
+
+    #[futures_test::test]
+    async fn blocked_peers_are_ignored_and_peers_can_be_disconnected() {
+        let network = LoopbackNetwork::default();
+        let build = || {
+            WebRtcSocketBuilder::new("room")
+                .loopback(&network)
+                .add_channel(ChannelConfig::reliable())
+                .build()
+        };
+        let (_blocked, blocked_loop) = build();
+        let (mut socket, socket_loop) = build();
+        let (mut other, other_loop) = build();
+        // Ids are handed out in the order the sockets join, which is the order the loops are
+        // first polled in
+        let [blocked_id, socket_id, other_id] =
+            [1, 2, 3].map(|id| PeerId(uuid::Uuid::from_u128(id)));
+        socket.block_peer(blocked_id);
+        let mut loops = future::join3(blocked_loop, socket_loop, other_loop).fuse();
+
+        let test = async {
+            // The blocked peer's offer is ignored, while the other peer connects
+            loop {
+                let (peer, state) = socket.next_peer_event().await.unwrap();
+                assert_ne!(peer, blocked_id);
+                if state == PeerState::Connected {
+                    assert_eq!(peer, other_id);
+                    break;
+                }
+            }
+
+            socket.disconnect_peer(other_id);
+            while socket.next_peer_event().await != Some((other_id, PeerState::Disconnected)) {}
+            while other.next_peer_event().await != Some((socket_id, PeerState::Disconnected)) {}
+            assert_eq!(socket.connected_peers().count(), 0);
+        }
+        .fuse();
+
+        futures::select! {
+            _ = loops => panic!("message loops finished early"),
+            _ = Box::pin(test) => {}
+        }
+    }
+
+    #[futures_test::test]
+    async fn peer_filter_and_max_peers_limit_connections() {
+        let network = LoopbackNetwork::default();
+        let build = |name: &str| {
+            WebRtcSocketBuilder::new("room")
+                .loopback(&network)
+                .metadata(json!({ "name": name }))
+        };
+        let candidates = Arc::new(Mutex::new(Vec::new()));
+        let seen = candidates.clone();
+        let (_first, first_loop) = build("first")
+            .add_channel(ChannelConfig::reliable())
+            .build();
+        let (_second, second_loop) = build("second")
+            .add_channel(ChannelConfig::reliable())
+            .build();
+        let (mut filtering, filtering_loop) = build("filtering")
+            .peer_filter(move |peer| {
+                seen.lock().unwrap().push(peer.clone());
+                peer.metadata != Some(json!({ "name": "first" }))
+            })
+            .add_channel(ChannelConfig::reliable())
+            .build();
+        let (mut limited, limited_loop) = build("limited")
+            .max_peers(Some(1))
+            .add_channel(ChannelConfig::reliable())
+            .build();
+        let [first_id, second_id] = [1, 2].map(|id| PeerId(uuid::Uuid::from_u128(id)));
+        let mut loops = future::join4(first_loop, second_loop, filtering_loop, limited_loop).fuse();
+
+        let test = async {
+            // The first peer's offer is rejected because of its metadata
+            loop {
+                let (peer, state) = filtering.next_peer_event().await.unwrap();
+                assert_ne!(peer, first_id);
+                if state == PeerState::Connected && peer == second_id {
+                    break;
+                }
+            }
+            assert!(candidates.lock().unwrap().iter().any(|candidate| {
+                candidate.id == first_id
+                    && candidate.incoming
+                    && candidate.metadata == Some(json!({ "name": "first" }))
+            }));
+
+            // Every peer offers to connect to the last one, which only accepts one of them
+            let peer = loop {
+                if let Some((peer, PeerState::Connected)) = limited.next_peer_event().await {
+                    break peer;
+                }
+            };
+            Delay::new(Duration::from_millis(100)).await;
+            assert!(limited.update_peers().iter().all(|(id, _)| *id == peer));
+            assert_eq!(limited.connected_peers().collect::<Vec<_>>(), [peer]);
+        }
+        .fuse();
+
+        futures::select! {
+            _ = loops => panic!("message loops finished early"),
+            _ = Box::pin(test) => {}
+        }
+    }
+}
@@ -3,6 +3,18 @@ use serde::{Deserialize, Serialize};
 /// Events go from signaling server to peer
 pub type SignalEvent = matchbox_protocol::SignalEvent<PeerSignal>;
 
+/// Events concerning the connection to the signaling server, see
+/// [`WebRtcSocket::update_signals`](crate::WebRtcSocket::update_signals)
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum SignalingEvent {
+    /// An event sent by the signaling server
+    Server(SignalEvent),
+    /// The connection to the signaling server was lost and the socket is trying to reconnect
+    Lost,
+    /// The connection to the signaling server was re-established after [`SignalingEvent::Lost`]
+    Restored,
+}
+
 /// Requests go from peer to signaling server
 pub type PeerRequest = matchbox_protocol::PeerRequest<PeerSignal>;
 
@@ -12,4 +24,6 @@ pub enum PeerSignal {
     IceCandidate(String),
     Offer(String),
     Answer(String),
+    /// Application data sent before an offer, see [`crate::WebRtcSocketBuilder::metadata`]
+    Metadata(serde_json::Value),
 }
@@ -1,77 +1,242 @@
+mod conditioner;
 pub(crate) mod error;
+mod framing;
+#[cfg(all(feature = "loopback", not(target_arch = "wasm32")))]
+pub(crate) mod loopback;
 mod messages;
+mod send_queue;
 mod signal_peer;
 mod socket;
+mod stats;
 
 use self::error::SignalingError;
 use crate::{webrtc_socket::signal_peer::SignalPeer, Error};
 use async_trait::async_trait;
 use cfg_if::cfg_if;
+use conditioner::DelayedPackets;
+pub use conditioner::{LinkConditions, NetworkConditioner};
 use futures::{future::Either, stream::FuturesUnordered, Future, FutureExt, StreamExt};
 use futures_channel::mpsc::{UnboundedReceiver, UnboundedSender};
 use futures_timer::Delay;
 use futures_util::select;
 use log::{debug, error, info, warn};
-use matchbox_protocol::{PeerEvent, PeerId};
+#[cfg(all(feature = "loopback", not(target_arch = "wasm32")))]
+pub use loopback::LoopbackNetwork;
+use matchbox_protocol::{
+    PeerEvent, PeerId, ResumeToken, RESUMABLE_QUERY_PARAM, RESUME_TOKEN_QUERY_PARAM,
+};
+pub use messages::SignalingEvent;
 use messages::*;
-pub(crate) use socket::MessageLoopChannels;
+use send_queue::QueuedPacket;
 pub use socket::{
-    BuildablePlurality, ChannelConfig, ChannelPlurality, MultipleChannels, NoChannels, PeerState,
-    RtcIceServerConfig, SingleChannel, WebRtcChannel, WebRtcSocket, WebRtcSocketBuilder,
+    BuildablePlurality, ChannelConfig, ChannelPlurality, ConnectionFailure, MultipleChannels,
+    NoChannels, PeerCandidate, PeerState, RtcIceServerConfig, RtcIceTransportPolicy,
+    SignalingReconnectPolicy, SingleChannel, WebRtcChannel, WebRtcSocket, WebRtcSocketBuilder,
+};
+#[cfg(not(target_arch = "wasm32"))]
+pub use socket::{ClientCertificate, SignalingTlsConfig};
+pub(crate) use socket::{IceConfig, MessageLoopChannels, PeerPolicy, SocketCommand};
+pub use stats::{ChannelStats, IceCandidateType, PeerStats};
+use std::{
+    collections::{HashMap, HashSet},
+    pin::Pin,
+    sync::Arc,
+    time::Duration,
 };
-use std::{collections::HashMap, pin::Pin, time::Duration};
 
 cfg_if! {
     if #[cfg(target_arch = "wasm32")] {
         mod wasm;
         type UseMessenger = wasm::WasmMessenger;
-        type UseSignaller = wasm::WasmSignaller;
+        pub(crate) type UseSignallerBuilder = wasm::WasmSignallerBuilder;
         /// A future which runs the message loop for the socket and completes
         /// when the socket closes or disconnects
         pub type MessageLoopFuture = Pin<Box<dyn Future<Output = Result<(), Error>>>>;
     } else {
         mod native;
         type UseMessenger = native::NativeMessenger;
-        type UseSignaller = native::NativeSignaller;
+        pub(crate) type UseSignallerBuilder = native::NativeSignallerBuilder;
         /// A future which runs the message loop for the socket and completes
         /// when the socket closes or disconnects
         pub type MessageLoopFuture = Pin<Box<dyn Future<Output = Result<(), Error>> + Send>>;
     }
 }
 
+cfg_if! {
+    if #[cfg(target_arch = "wasm32")] {
+        /// Implemented by all types, as nothing has to be [`Send`] on wasm
+        pub trait MaybeSend {}
+        impl<T> MaybeSend for T {}
+
+        /// Implemented by all types, as nothing has to be [`Sync`] on wasm
+        pub trait MaybeSync {}
+        impl<T> MaybeSync for T {}
+    } else {
+        /// Implemented by all [`Send`] types, as the message loop runs on a multi-threaded
+        /// runtime on native
+        pub trait MaybeSend: Send {}
+        impl<T: Send> MaybeSend for T {}
+
+        /// Implemented by all [`Sync`] types, as the message loop runs on a multi-threaded
+        /// runtime on native
+        pub trait MaybeSync: Sync {}
+        impl<T: Sync> MaybeSync for T {}
+    }
+}
+
+/// A connection to a signaling server, used to exchange the messages needed to connect to peers.
+///
+/// By default, [`WebRtcSocket`] connects to a matchbox signaling server over a websocket. Implement
+/// this trait together with [`SignallerBuilder`] to exchange the same messages over any other
+/// transport, e.g. an existing connection to a game server or an in-process channel.
+///
+/// Messages are JSON encoded requests and events of the matchbox protocol, and have to be
+/// delivered in order.
 #[cfg_attr(not(target_arch = "wasm32"), async_trait)]
 #[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
-trait Signaller: Sized {
-    async fn new(mut attempts: Option<u16>, room_url: &str) -> Result<Self, SignalingError>;
-
+pub trait Signaller: MaybeSend + 'static {
+    /// Sends a request to the signaling server
     async fn send(&mut self, request: String) -> Result<(), SignalingError>;
 
+    /// Waits for the next message from the signaling server.
+    ///
+    /// Returning [`SignalingError::UnknownFormat`] skips the message, any other error is treated
+    /// as a lost connection.
     async fn next_message(&mut self) -> Result<String, SignalingError>;
 }
 
-async fn signaling_loop<S: Signaller>(
+/// Creates [`Signaller`]s for a [`WebRtcSocket`].
+///
+/// See also: [`WebRtcSocketBuilder::signaller_builder`]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+pub trait SignallerBuilder: std::fmt::Debug + MaybeSend + MaybeSync + 'static {
+    /// Connects to the signaling server for the given room, making at most `attempts`
+    /// attempts, or retrying indefinitely if `None`.
+    ///
+    /// The room url asks the server for a resume token through a query parameter. This is called
+    /// again with the same room url when the connection is lost, with the resume token added as
+    /// another query parameter if the server gave us one.
+    async fn new_signaller(
+        &self,
+        attempts: Option<u16>,
+        room_url: String,
+    ) -> Result<Box<dyn Signaller>, SignalingError>;
+}
+
+async fn signaling_loop(
+    builder: Arc<dyn SignallerBuilder>,
     attempts: Option<u16>,
+    reconnect_policy: Option<SignalingReconnectPolicy>,
     room_url: String,
+    strict: bool,
     mut requests_receiver: futures_channel::mpsc::UnboundedReceiver<PeerRequest>,
-    events_sender: futures_channel::mpsc::UnboundedSender<SignalEvent>,
+    events_sender: futures_channel::mpsc::UnboundedSender<SignalingEvent>,
+) -> Result<(), SignalingError> {
+    let room_url = with_query_param(&room_url, RESUMABLE_QUERY_PARAM, "true");
+    let mut signaller = builder.new_signaller(attempts, room_url.clone()).await?;
+    // A request that couldn't be delivered because the connection was lost
+    let mut unsent_request = None;
+    // Lets us keep our id when reconnecting
+    let mut resume_token = None;
+
+    loop {
+        let err = match relay_signals(
+            signaller.as_mut(),
+            &mut unsent_request,
+            &mut resume_token,
+            strict,
+            &mut requests_receiver,
+            &events_sender,
+        )
+        .await
+        {
+            Ok(()) => break Ok(()),
+            // The socket was dropped, nobody is listening for events anymore
+            Err(err @ SignalingError::UndeliverableSignal(_)) => break Err(err),
+            // Reconnecting to the same server won't make it speak our protocol
+            Err(err @ SignalingError::MalformedMessage { .. }) => break Err(err),
+            Err(err) => err,
+        };
+
+        let Some(policy) = &reconnect_policy else {
+            break Err(err);
+        };
+
+        warn!("lost connection to signaling server: {err}");
+        events_sender
+            .unbounded_send(SignalingEvent::Lost)
+            .map_err(SignalingError::from)?;
+
+        let reconnect_url = match resume_token {
+            Some(token) => resume_url(&room_url, token),
+            None => room_url.clone(),
+        };
+        signaller = reconnect_signaller(builder.as_ref(), policy, &reconnect_url).await?;
+
+        info!("reconnected to signaling server");
+        events_sender
+            .unbounded_send(SignalingEvent::Restored)
+            .map_err(SignalingError::from)?;
+    }
+}
+
+/// Relays requests and events between the signaller and the message loop until either side
+/// closes or fails.
+///
+/// Messages that aren't valid events are skipped, or fail the relay if `strict` is set.
+async fn relay_signals(
+    signaller: &mut dyn Signaller,
+    unsent_request: &mut Option<String>,
+    resume_token: &mut Option<ResumeToken>,
+    strict: bool,
+    requests_receiver: &mut futures_channel::mpsc::UnboundedReceiver<PeerRequest>,
+    events_sender: &futures_channel::mpsc::UnboundedSender<SignalingEvent>,
 ) -> Result<(), SignalingError> {
-    let mut signaller = S::new(attempts, &room_url).await?;
+    if let Some(request) = unsent_request.take() {
+        debug!("-> {request} (resending after reconnect)");
+        if let Err(err) = signaller.send(request.clone()).await {
+            *unsent_request = Some(request);
+            return Err(err);
+        }
+    }
 
     loop {
         select! {
             request = requests_receiver.next().fuse() => {
+                let Some(request) = request else {
+                    // The message loop is done, and so are we
+                    break Ok(());
+                };
                 let request = serde_json::to_string(&request).expect("serializing request");
                 debug!("-> {request}");
-                signaller.send(request).await.map_err(SignalingError::from)?;
+                if let Err(err) = signaller.send(request.clone()).await {
+                    *unsent_request = Some(request);
+                    break Err(err);
+                }
             }
 
             message = signaller.next_message().fuse() => {
                 match message {
                     Ok(message) => {
                         debug!("Received {message}");
-                        let event: SignalEvent = serde_json::from_str(&message)
-                            .unwrap_or_else(|err| panic!("couldn't parse peer event: {err}.\nEvent: {message}"));
-                        events_sender.unbounded_send(event).map_err(SignalingError::from)?;
+                        let event: SignalEvent = match serde_json::from_str(&message) {
+                            Ok(event) => event,
+                            Err(source) => {
+                                let err = SignalingError::MalformedMessage { message, source };
+                                if strict {
+                                    break Err(err);
+                                }
+                                warn!("ignoring {err}");
+                                continue;
+                            }
+                        };
+                        if let SignalEvent::Peer(PeerEvent::ResumeToken(token)) = &event {
+                            *resume_token = Some(*token);
+                        }
+                        events_sender
+                            .unbounded_send(SignalingEvent::Server(event))
+                            .map_err(SignalingError::from)?;
                     }
                     Err(SignalingError::UnknownFormat) => {
                         warn!("ignoring unexpected non-text message from signaling server")
@@ -80,8 +245,61 @@ async fn signaling_loop<S: Signaller>(
                 }
 
             }
+        }
+    }
+}
 
-            complete => break Ok(())
+/// Appends the resume token to the room url, so the server can give us our old id back
+fn resume_url(room_url: &str, token: ResumeToken) -> String {
+    with_query_param(room_url, RESUME_TOKEN_QUERY_PARAM, &token.to_string())
+}
+
+/// Appends a query parameter to the room url, percent-encoding the value
+pub(crate) fn with_query_param(room_url: &str, name: &str, value: &str) -> String {
+    let separator = if room_url.contains('?') { '&' } else { '?' };
+    let mut url = format!("{room_url}{separator}{name}=");
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                url.push(byte as char)
+            }
+            _ => url.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    url
+}
+
+/// Tries to connect to the signaling server again according to the given policy.
+async fn reconnect_signaller(
+    builder: &dyn SignallerBuilder,
+    policy: &SignalingReconnectPolicy,
+    room_url: &str,
+) -> Result<Box<dyn Signaller>, SignalingError> {
+    let mut attempt = 0;
+    loop {
+        let delay = policy.delay(attempt);
+        info!("reconnecting to signaling server in {delay:?}...");
+        Delay::new(delay).await;
+
+        match builder.new_signaller(Some(1), room_url.to_string()).await {
+            Ok(signaller) => break Ok(signaller),
+            Err(err) => {
+                attempt = attempt.saturating_add(1);
+                match policy.max_attempts {
+                    Some(max_attempts) if attempt >= max_attempts => {
+                        error!(
+                            "giving up reconnecting to signaling server after {attempt} attempt(s)"
+                        );
+                        // Unwrap the negotiation failure, as this is a disconnect rather than a
+                        // failure to establish the initial connection.
+                        break Err(match err {
+                            SignalingError::NegotiationFailed(err) => *err,
+                            err => err,
+                        });
+                    }
+                    _ => warn!("reconnection attempt {attempt} failed: {err}"),
+                }
+            }
         }
     }
 }
@@ -100,7 +318,11 @@ struct PacketSendError {
 }
 
 trait PeerDataSender {
-    fn send(&mut self, packet: Packet) -> Result<(), PacketSendError>;
+    fn send(&mut self, packet: QueuedPacket) -> Result<(), PacketSendError>;
+
+    /// Closes the data channel, which has to make the peer loop finish, unless dropping the
+    /// sender already does.
+    fn close(&mut self) {}
 }
 
 struct HandshakeResult<D: PeerDataSender, M> {
@@ -116,30 +338,59 @@ trait Messenger {
     type HandshakeMeta: Send;
 
     async fn offer_handshake(
+        &self,
         signal_peer: SignalPeer,
         mut peer_signal_rx: UnboundedReceiver<PeerSignal>,
         messages_from_peers_tx: Vec<UnboundedSender<(PeerId, Packet)>>,
-        ice_server_config: &RtcIceServerConfig,
+        ice_config: &IceConfig,
         channel_configs: &[ChannelConfig],
     ) -> HandshakeResult<Self::DataChannel, Self::HandshakeMeta>;
 
     async fn accept_handshake(
+        &self,
         signal_peer: SignalPeer,
         peer_signal_rx: UnboundedReceiver<PeerSignal>,
         messages_from_peers_tx: Vec<UnboundedSender<(PeerId, Packet)>>,
-        ice_server_config: &RtcIceServerConfig,
+        ice_config: &IceConfig,
         channel_configs: &[ChannelConfig],
     ) -> HandshakeResult<Self::DataChannel, Self::HandshakeMeta>;
 
-    async fn peer_loop(peer_uuid: PeerId, handshake_meta: Self::HandshakeMeta) -> PeerId;
+    async fn peer_loop(
+        peer_uuid: PeerId,
+        handshake_meta: Self::HandshakeMeta,
+        stats_interval: Option<Duration>,
+        peer_stats_tx: UnboundedSender<(PeerId, PeerStats)>,
+    ) -> PeerId;
 }
 
+/// Waits for a handshake to complete, giving up on the peer once the timeout expires.
+async fn handshake_with_timeout<D: PeerDataSender, M>(
+    peer_id: PeerId,
+    timeout: Option<Duration>,
+    handshake: impl Future<Output = HandshakeResult<D, M>>,
+) -> Result<HandshakeResult<D, M>, (PeerId, ConnectionFailure)> {
+    let Some(timeout) = timeout else {
+        return Ok(handshake.await);
+    };
+
+    select! {
+        result = handshake.fuse() => Ok(result),
+        _ = Delay::new(timeout).fuse() => Err((peer_id, ConnectionFailure::HandshakeTimeout(timeout))),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn message_loop<M: Messenger>(
+    messenger: &M,
     id_tx: futures_channel::oneshot::Sender<PeerId>,
-    ice_server_config: &RtcIceServerConfig,
+    ice_config: &IceConfig,
     channel_configs: &[ChannelConfig],
     channels: MessageLoopChannels,
     keep_alive_interval: Option<Duration>,
+    handshake_timeout: Option<Duration>,
+    stats_interval: Option<Duration>,
+    conditioner: Option<&NetworkConditioner>,
+    peer_policy: &PeerPolicy,
 ) -> Result<(), SignalingError> {
     let MessageLoopChannels {
         requests_sender,
@@ -148,13 +399,28 @@ async fn message_loop<M: Messenger>(
         messages_from_peers_tx,
         signal_tx,
         peer_state_tx,
+        peer_stats_tx,
+        connected_peers,
+        mut commands_rx,
     } = channels;
 
     let mut handshakes = FuturesUnordered::new();
     let mut peer_loops = FuturesUnordered::new();
     let mut handshake_signals = HashMap::new();
     let mut data_channels = HashMap::new();
+    // Peers we won't connect to, and peers to disconnect from once their handshake is done
+    let mut blocked_peers = HashSet::new();
+    let mut dropped_handshakes = HashSet::new();
+    // Peers whose offer we rejected, and metadata received from peers before their offer
+    let mut rejected_peers = HashSet::new();
+    let mut peer_metadata = HashMap::new();
+    // The latest peer loop for each peer, so an older one finishing late doesn't tear down a new
+    // connection to the same peer
+    let mut peer_loop_ids = HashMap::new();
+    let mut next_peer_loop_id = 0;
+    let mut delayed_packets = DelayedPackets::default();
     let mut id_tx = Option::Some(id_tx);
+    let mut own_id = None;
 
     let mut timeout = if let Some(interval) = keep_alive_interval {
         Either::Left(Delay::new(interval))
@@ -188,36 +454,122 @@ async fn message_loop<M: Messenger>(
             message = events_receiver.next().fuse() => {
                 if let Some(event) = message {
                     signal_tx.unbounded_send(event.clone()).expect("failed to send signal");
+                    let SignalingEvent::Server(event) = event else {
+                        continue;
+                    };
                     match event {
                         SignalEvent::Peer(PeerEvent::IdAssigned(peer_uuid)) => {
-                            if id_tx.take().expect("already sent peer id").send(peer_uuid.to_owned()).is_err() {
+                            let Some(id_tx) = id_tx.take() else {
+                                // We reconnected to the signaling server. Unless our session could
+                                // be resumed, we were given a new id. Peers we're already
+                                // connected to are unaffected either way.
+                                if own_id != Some(peer_uuid) {
+                                    warn!("signaling server assigned a new id after reconnecting: {peer_uuid}");
+                                }
+                                continue;
+                            };
+                            own_id = Some(peer_uuid);
+                            if id_tx.send(peer_uuid.to_owned()).is_err() {
                                 // Socket receiver was dropped, exit cleanly.
                                 break Ok(());
                             };
                         },
                         SignalEvent::Peer(PeerEvent::NewPeer(peer_uuid)) => {
+                            if blocked_peers.contains(&peer_uuid) {
+                                debug!("not connecting to blocked peer {peer_uuid}");
+                                continue;
+                            }
+                            let peers = handshakes.len() + data_channels.len();
+                            if peer_policy.is_full(peers) {
+                                debug!("not connecting to {peer_uuid}, already connected or connecting to {peers} peers");
+                                continue;
+                            }
+                            let candidate = PeerCandidate {
+                                id: peer_uuid,
+                                incoming: false,
+                                metadata: None,
+                                peers,
+                            };
+                            if !peer_policy.accepts(&candidate) {
+                                debug!("not connecting to rejected peer {peer_uuid}");
+                                continue;
+                            }
                             let (signal_tx, signal_rx) = futures_channel::mpsc::unbounded();
                             handshake_signals.insert(peer_uuid, signal_tx);
                             let signal_peer = SignalPeer::new(peer_uuid, requests_sender.clone());
-                            handshakes.push(M::offer_handshake(signal_peer, signal_rx, messages_from_peers_tx.clone(), ice_server_config, channel_configs))
+                            if let Some(metadata) = &peer_policy.metadata {
+                                signal_peer.send(PeerSignal::Metadata(metadata.clone()));
+                            }
+                            let handshake = messenger.offer_handshake(signal_peer, signal_rx, messages_from_peers_tx.clone(), ice_config, channel_configs);
+                            handshakes.push(handshake_with_timeout(peer_uuid, handshake_timeout, handshake));
+                            if peer_state_tx.unbounded_send((peer_uuid, PeerState::Connecting)).is_err() {
+                                // socket dropped, exit cleanly
+                                break Ok(());
+                            }
                         },
                         SignalEvent::Peer(PeerEvent::PeerLeft(peer_uuid)) => {
+                            rejected_peers.remove(&peer_uuid);
+                            peer_metadata.remove(&peer_uuid);
                             if peer_state_tx.unbounded_send((peer_uuid, PeerState::Disconnected)).is_err() {
                                 // socket dropped, exit cleanly
                                 break Ok(());
                             }
                         },
                         SignalEvent::Peer(PeerEvent::Signal { sender, data }) => {
+                            if blocked_peers.contains(&sender) {
+                                debug!("ignoring signal from blocked peer {sender}");
+                                continue;
+                            }
+                            if rejected_peers.contains(&sender) {
+                                debug!("ignoring signal from rejected peer {sender}");
+                                continue;
+                            }
+                            if let PeerSignal::Metadata(metadata) = data {
+                                peer_metadata.insert(sender, metadata);
+                                continue;
+                            }
+                            if !handshake_signals.contains_key(&sender) {
+                                if !matches!(data, PeerSignal::Offer(_)) {
+                                    // E.g. a late candidate from a handshake we already gave up on
+                                    debug!("ignoring signal from {sender}, who hasn't sent an offer");
+                                    continue;
+                                }
+                                let peers = handshakes.len() + data_channels.len();
+                                if peer_policy.is_full(peers) {
+                                    // Not remembered, so the peer can try again once there is room
+                                    debug!("turning down offer from {sender}, already connected or connecting to {peers} peers");
+                                    continue;
+                                }
+                                let candidate = PeerCandidate {
+                                    id: sender,
+                                    incoming: true,
+                                    metadata: peer_metadata.remove(&sender),
+                                    peers,
+                                };
+                                if !peer_policy.accepts(&candidate) {
+                                    debug!("rejecting offer from {sender}");
+                                    rejected_peers.insert(sender);
+                                    continue;
+                                }
+                            }
+                            let mut accepted = false;
                             let signal_tx = handshake_signals.entry(sender).or_insert_with(|| {
                                 let (from_peer_tx, peer_signal_rx) = futures_channel::mpsc::unbounded();
                                 let signal_peer = SignalPeer::new(sender, requests_sender.clone());
-                                handshakes.push(M::accept_handshake(signal_peer, peer_signal_rx, messages_from_peers_tx.clone(), ice_server_config, channel_configs));
+                                let handshake = messenger.accept_handshake(signal_peer, peer_signal_rx, messages_from_peers_tx.clone(), ice_config, channel_configs);
+                                handshakes.push(handshake_with_timeout(sender, handshake_timeout, handshake));
+                                accepted = true;
                                 from_peer_tx
                             });
 
                             if signal_tx.unbounded_send(data).is_err() {
                                 warn!("ignoring signal from peer {sender} because the handshake has already finished");
                             }
+
+                            if accepted && peer_state_tx.unbounded_send((sender, PeerState::Connecting)).is_err() {
+                                // socket dropped, exit cleanly
+                                break Ok(());
+                            }
                         },
                         _ => {}
                     }
@@ -225,16 +577,56 @@ async fn message_loop<M: Messenger>(
             }
 
             handshake_result = handshakes.select_next_some() => {
+                let handshake_result = match handshake_result {
+                    Ok(handshake_result) => handshake_result,
+                    Err((peer_uuid, reason)) => {
+                        warn!("failed to connect to peer {peer_uuid}: {reason}");
+                        dropped_handshakes.remove(&peer_uuid);
+                        // Allow a new handshake, in case the peer tries again
+                        handshake_signals.remove(&peer_uuid);
+                        if peer_state_tx.unbounded_send((peer_uuid, PeerState::Failed { reason })).is_err() {
+                            // socket dropped, exit cleanly
+                            break Ok(());
+                        }
+                        continue;
+                    }
+                };
+                let peer_uuid = handshake_result.peer_id;
+                if dropped_handshakes.remove(&peer_uuid) || blocked_peers.contains(&peer_uuid) {
+                    debug!("disconnecting from {peer_uuid} right after the handshake");
+                    handshake_signals.remove(&peer_uuid);
+                    for mut data_channel in handshake_result.data_channels {
+                        data_channel.close();
+                    }
+                    // The peer loop closes the connection, then reports the peer as disconnected
+                    next_peer_loop_id += 1;
+                    peer_loop_ids.insert(peer_uuid, next_peer_loop_id);
+                    let peer_loop = M::peer_loop(peer_uuid, handshake_result.metadata, stats_interval, peer_stats_tx.clone());
+                    peer_loops.push(with_peer_loop_id(peer_loop, next_peer_loop_id));
+                    continue;
+                }
                 data_channels.insert(handshake_result.peer_id, handshake_result.data_channels);
+                connected_peers.insert(handshake_result.peer_id);
                 if peer_state_tx.unbounded_send((handshake_result.peer_id, PeerState::Connected)).is_err() {
                     // sending can only fail on socket drop, in which case connected_peers is unavailable, ignore
                     break Ok(());
                 }
-                peer_loops.push(M::peer_loop(handshake_result.peer_id, handshake_result.metadata));
+                next_peer_loop_id += 1;
+                peer_loop_ids.insert(handshake_result.peer_id, next_peer_loop_id);
+                let peer_loop = M::peer_loop(handshake_result.peer_id, handshake_result.metadata, stats_interval, peer_stats_tx.clone());
+                peer_loops.push(with_peer_loop_id(peer_loop, next_peer_loop_id));
             }
 
-            peer_uuid = peer_loops.select_next_some() => {
+            finished = peer_loops.select_next_some() => {
+                let (peer_uuid, peer_loop_id) = finished;
                 debug!("peer {peer_uuid} finished");
+                if peer_loop_ids.get(&peer_uuid) != Some(&peer_loop_id) {
+                    debug!("already reconnected to {peer_uuid}, keeping the new connection");
+                    continue;
+                }
+                peer_loop_ids.remove(&peer_uuid);
+                data_channels.remove(&peer_uuid);
+                connected_peers.remove(peer_uuid);
                 if peer_state_tx.unbounded_send((peer_uuid, PeerState::Disconnected)).is_err() {
                     // sending can only fail on socket drop, in which case connected_peers is unavailable, ignore
                     break Ok(());
@@ -244,16 +636,19 @@ async fn message_loop<M: Messenger>(
             message = next_peer_message_out => {
                 match message {
                     Some((channel_index, Some((peer, packet)))) => {
-                        let data_channel = data_channels
-                            .get_mut(&peer)
-                            .expect("couldn't find data channel for peer")
-                            .get_mut(channel_index).unwrap_or_else(|| panic!("couldn't find data channel with index {channel_index}"));
-                        if let Err(e) = data_channel.send(packet) {
-                            // Peer we're sending to closed their end of the connection.
-                            // We anticipate the PeerLeft event soon, but we sent a message before it came.
-                            // Do nothing. Only log it.
-                            warn!("failed to send to peer {peer} (socket closed): {e:?}");
+                        let Some(conditioner) = conditioner else {
+                            send_to_peer(&mut data_channels, channel_configs, channel_index, peer, packet);
+                            continue;
                         };
+                        let reliable = channel_configs[channel_index].is_reliable();
+                        let delays = conditioner.delays(channel_index, reliable, peer);
+                        let mut copies: Vec<_> = (1..delays.len()).map(|_| packet.duplicate()).collect();
+                        copies.push(packet);
+                        for (delay, packet) in delays.into_iter().zip(copies) {
+                            if let Some(packet) = delayed_packets.push(channel_index, peer, packet, delay, reliable) {
+                                send_to_peer(&mut data_channels, channel_configs, channel_index, peer, packet);
+                            }
+                        }
                     }
                     Some((_, None)) | None => {
                         // Receiver end of outgoing message channel closed,
@@ -266,7 +661,100 @@ async fn message_loop<M: Messenger>(
                 }
             }
 
+            command = commands_rx.select_next_some() => match command {
+                SocketCommand::Close => {
+                    debug!("closing socket");
+                    if requests_sender.unbounded_send(PeerRequest::Leave).is_err() {
+                        warn!("couldn't tell the signaling server that we left");
+                    }
+                    // Let the peer loops close their connections
+                    for data_channel in data_channels.values_mut().flatten() {
+                        data_channel.close();
+                    }
+                    data_channels.clear();
+                    while let Some((peer_uuid, _)) = peer_loops.next().await {
+                        connected_peers.remove(peer_uuid);
+                        // The socket may have been dropped in the meantime, which is fine
+                        let _ = peer_state_tx.unbounded_send((peer_uuid, PeerState::Disconnected));
+                    }
+                    break Ok(());
+                }
+                SocketCommand::DisconnectPeer(peer_uuid) => {
+                    disconnect_peer(peer_uuid, &mut data_channels, &mut handshake_signals, &mut dropped_handshakes);
+                }
+                SocketCommand::BlockPeer(peer_uuid) => {
+                    blocked_peers.insert(peer_uuid);
+                    disconnect_peer(peer_uuid, &mut data_channels, &mut handshake_signals, &mut dropped_handshakes);
+                }
+                SocketCommand::UnblockPeer(peer_uuid) => {
+                    blocked_peers.remove(&peer_uuid);
+                }
+            },
+
+            (channel_index, peer, packet) = delayed_packets.select_next_some() => {
+                send_to_peer(&mut data_channels, channel_configs, channel_index, peer, packet);
+            }
+
             complete => break Ok(())
         }
     }
 }
+
+/// Tags a peer loop with an id that tells it apart from other connections to the same peer
+fn with_peer_loop_id(
+    peer_loop: impl Future<Output = PeerId>,
+    id: u64,
+) -> impl Future<Output = (PeerId, u64)> {
+    peer_loop.map(move |peer| (peer, id))
+}
+
+/// Closes the connection to a peer, or marks it to be closed once its handshake is done.
+///
+/// Once the data channels are closed, the peer loop finishes and reports the peer as disconnected.
+fn disconnect_peer<D: PeerDataSender>(
+    peer: PeerId,
+    data_channels: &mut HashMap<PeerId, Vec<D>>,
+    handshake_signals: &mut HashMap<PeerId, UnboundedSender<PeerSignal>>,
+    dropped_handshakes: &mut HashSet<PeerId>,
+) {
+    if let Some(mut channels) = data_channels.remove(&peer) {
+        debug!("disconnecting from {peer}");
+        for data_channel in &mut channels {
+            data_channel.close();
+        }
+        // Allow a new handshake, in case the peer reconnects and isn't blocked
+        handshake_signals.remove(&peer);
+    } else if handshake_signals.contains_key(&peer) {
+        dropped_handshakes.insert(peer);
+    }
+}
+
+fn send_to_peer<D: PeerDataSender>(
+    data_channels: &mut HashMap<PeerId, Vec<D>>,
+    channel_configs: &[ChannelConfig],
+    channel_index: usize,
+    peer: PeerId,
+    packet: QueuedPacket,
+) {
+    let Some(data_channels) = data_channels.get_mut(&peer) else {
+        // We disconnected from the peer, but the socket didn't know yet when sending
+        debug!("dropping packet for disconnected peer {peer}");
+        return;
+    };
+    let data_channel = data_channels
+        .get_mut(channel_index)
+        .unwrap_or_else(|| panic!("couldn't find data channel with index {channel_index}"));
+    let result = match channel_configs[channel_index].fragmentation() {
+        Some(fragment_size) => packet
+            .into_fragments(fragment_size)
+            .into_iter()
+            .try_for_each(|fragment| data_channel.send(fragment)),
+        None => data_channel.send(packet),
+    };
+    if let Err(e) = result {
+        // Peer we're sending to closed their end of the connection.
+        // We anticipate the PeerLeft event soon, but we sent a message before it came.
+        // Do nothing. Only log it.
+        warn!("failed to send to peer {peer} (socket closed): {e:?}");
+    };
+}
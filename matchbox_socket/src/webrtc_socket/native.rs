@@ -1,57 +1,291 @@
-use super::{HandshakeResult, PacketSendError, PeerDataSender};
+use super::{send_queue::QueuedPacket, HandshakeResult, PacketSendError, PeerDataSender};
 use crate::{
     webrtc_socket::{
         error::SignalingError,
         messages::PeerSignal,
         signal_peer::SignalPeer,
         socket::{create_data_channels_ready_fut, new_senders_and_receivers},
-        ChannelConfig, Messenger, Packet, Signaller,
+        stats::stats_timer,
+        ChannelConfig, ChannelStats, IceCandidateType, IceConfig, Messenger, Packet, PeerStats,
+        SignalingTlsConfig, Signaller, SignallerBuilder,
     },
-    RtcIceServerConfig,
+    RtcIceTransportPolicy,
 };
+#[cfg(feature = "async-std")]
 use async_compat::CompatExt;
 use async_trait::async_trait;
+#[cfg(feature = "tokio")]
+use async_tungstenite::tokio::TokioAdapter;
 use async_tungstenite::{
-    async_std::{connect_async, ConnectStream},
-    tungstenite::Message,
+    tungstenite::{
+        client::{uri_mode, IntoClientRequest},
+        error::UrlError,
+        handshake::client::Request,
+        http::{self, HeaderMap, HeaderName, HeaderValue},
+        stream::Mode,
+        Message,
+    },
     WebSocketStream,
 };
-use bytes::Bytes;
 use futures::{
     future::{Fuse, FusedFuture},
+    io::{AsyncRead, AsyncWrite},
     stream::FuturesUnordered,
     Future, FutureExt, SinkExt, StreamExt,
 };
 use futures_channel::mpsc::{Receiver, Sender, TrySendError, UnboundedReceiver, UnboundedSender};
+use futures_rustls::TlsConnector;
 use futures_timer::Delay;
 use futures_util::{lock::Mutex, select};
 use log::{debug, error, info, trace, warn};
 use matchbox_protocol::PeerId;
-use std::{pin::Pin, sync::Arc, time::Duration};
+use std::{
+    io,
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 use webrtc::{
     api::APIBuilder,
     data_channel::{data_channel_init::RTCDataChannelInit, RTCDataChannel},
+    ice::candidate::CandidateType,
     ice_transport::{
         ice_candidate::{RTCIceCandidate, RTCIceCandidateInit},
         ice_credential_type::RTCIceCredentialType,
         ice_server::RTCIceServer,
     },
     peer_connection::{
-        configuration::RTCConfiguration, sdp::session_description::RTCSessionDescription,
-        RTCPeerConnection,
+        configuration::RTCConfiguration, policy::ice_transport_policy::RTCIceTransportPolicy,
+        sdp::session_description::RTCSessionDescription, RTCPeerConnection,
     },
+    stats::StatsReportType,
 };
 
-pub(crate) struct NativeSignaller {
-    websocket_stream: WebSocketStream<ConnectStream>,
+/// Bytes buffered by a data channel above which packets are held back in the send queue
+const MAX_BUFFERED_AMOUNT: usize = 1024 * 1024;
+/// Bytes buffered by a data channel at which sending packets resumes
+const BUFFERED_AMOUNT_LOW_THRESHOLD: usize = 512 * 1024;
+
+/// A TCP connection to the signaling server, encrypted or not
+trait Connection: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Connection for T {}
+
+/// Opens a websocket connection, on tokio or async-std depending on [`runs_on_tokio`]
+async fn connect(
+    request: Request,
+    tls_connector: &TlsConnector,
+) -> Result<WebSocketStream<Box<dyn Connection>>, async_tungstenite::tungstenite::Error> {
+    let uri = request.uri();
+    let mode = uri_mode(uri)?;
+    let host = uri
+        .host()
+        .ok_or(UrlError::NoHostName)?
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_string();
+    let port = uri.port_u16().unwrap_or(match mode {
+        Mode::Plain => 80,
+        Mode::Tls => 443,
+    });
+
+    let tcp_stream = connect_tcp(&host, port).await?;
+    let stream: Box<dyn Connection> = match mode {
+        Mode::Plain => tcp_stream,
+        Mode::Tls => {
+            let server_name = rustls::ServerName::try_from(host.as_str())
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            Box::new(tls_connector.connect(server_name, tcp_stream).await?)
+        }
+    };
+    let (websocket_stream, _) = async_tungstenite::client_async(request, stream).await?;
+    Ok(websocket_stream)
+}
+
+/// Whether to run on tokio, which is always the case without the `async-std` feature, and
+/// otherwise only when the socket is polled from a tokio runtime
+#[cfg(feature = "tokio")]
+fn runs_on_tokio() -> bool {
+    !cfg!(feature = "async-std") || tokio::runtime::Handle::try_current().is_ok()
+}
+
+async fn connect_tcp(host: &str, port: u16) -> io::Result<Box<dyn Connection>> {
+    #[cfg(feature = "tokio")]
+    if runs_on_tokio() {
+        let tcp_stream = tokio::net::TcpStream::connect((host, port)).await?;
+        return Ok(Box::new(TokioAdapter::new(tcp_stream)));
+    }
+    #[cfg(feature = "async-std")]
+    {
+        let tcp_stream = async_std::net::TcpStream::connect((host, port)).await?;
+        Ok(Box::new(tcp_stream))
+    }
+    #[cfg(not(feature = "async-std"))]
+    unreachable!("everything runs on tokio without the async-std feature")
+}
+
+/// Runs a future of webrtc-rs, which needs a tokio runtime
+async fn on_tokio<F: Future>(future: F) -> F::Output {
+    #[cfg(feature = "tokio")]
+    if runs_on_tokio() {
+        return future.await;
+    }
+    // Required to run tokio futures with other async executors
+    #[cfg(feature = "async-std")]
+    {
+        future.compat().await
+    }
+    #[cfg(not(feature = "async-std"))]
+    unreachable!("everything runs on tokio without the async-std feature")
+}
+
+/// Closes the connection of a handshake that is dropped before completing, e.g. because it timed
+/// out, as webrtc-rs connections keep running until closed
+struct HandshakeGuard(Option<Arc<RTCPeerConnection>>);
+
+impl HandshakeGuard {
+    fn new(connection: &Arc<RTCPeerConnection>) -> Self {
+        Self(Some(connection.clone()))
+    }
+
+    /// The handshake completed, so the connection is closed by the peer loop instead
+    fn complete(mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for HandshakeGuard {
+    fn drop(&mut self) {
+        let Some(connection) = self.0.take() else {
+            return;
+        };
+        debug!("closing connection of an aborted handshake");
+        let close = async move {
+            if let Err(e) = connection.close().await {
+                warn!("failed to close connection of an aborted handshake: {e:?}");
+            }
+        };
+        #[cfg(feature = "tokio")]
+        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+            handle.spawn(close);
+            return;
+        }
+        #[cfg(feature = "async-std")]
+        async_std::task::spawn(close.compat());
+        #[cfg(not(feature = "async-std"))]
+        warn!("no tokio runtime to close the connection of an aborted handshake on");
+    }
+}
+
+pub(crate) struct NativeSignaller<S> {
+    websocket_stream: WebSocketStream<S>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct NativeSignallerBuilder {
+    /// Extra headers for the websocket request, e.g. for authentication
+    pub(crate) headers: Vec<(String, String)>,
+    /// TLS settings for `wss://` urls, `None` for the defaults
+    pub(crate) tls: Option<SignalingTlsConfig>,
+}
+
+impl NativeSignallerBuilder {
+    fn headers(&self) -> Result<HeaderMap, http::Error> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in &self.headers {
+            let name = HeaderName::try_from(name.as_str())?;
+            let value = HeaderValue::try_from(value.as_str())?;
+            headers.append(name, value);
+        }
+        Ok(headers)
+    }
+
+    fn tls_connector(&self) -> Result<TlsConnector, rustls::Error> {
+        let default_config = SignalingTlsConfig::default();
+        let config = self.tls.as_ref().unwrap_or(&default_config);
+        let mut roots = rustls::RootCertStore::empty();
+        if config.web_pki_roots {
+            roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|anchor| {
+                rustls::OwnedTrustAnchor::from_subject_spki_name_constraints(
+                    anchor.subject,
+                    anchor.spki,
+                    anchor.name_constraints,
+                )
+            }));
+        }
+        for certificate in &config.root_certificates {
+            roots.add(&rustls::Certificate(certificate.clone()))?;
+        }
+
+        let builder = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots);
+        let mut client_config = match &config.client_certificate {
+            Some(certificate) => builder.with_client_auth_cert(
+                certificate
+                    .chain
+                    .iter()
+                    .cloned()
+                    .map(rustls::Certificate)
+                    .collect(),
+                rustls::PrivateKey(certificate.private_key.clone()),
+            )?,
+            None => builder.with_no_client_auth(),
+        };
+        if config.danger_accept_invalid_certificates {
+            warn!("signaling server certificates are not verified");
+            client_config
+                .dangerous()
+                .set_certificate_verifier(Arc::new(AcceptAnyCertificate));
+        }
+        Ok(TlsConnector::from(Arc::new(client_config)))
+    }
+}
+
+/// Accepts any server certificate, see [`SignalingTlsConfig::danger_accept_invalid_certificates`]
+struct AcceptAnyCertificate;
+
+impl rustls::client::ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &rustls::Certificate,
+        _intermediates: &[rustls::Certificate],
+        _server_name: &rustls::ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<rustls::client::ServerCertVerified, rustls::Error> {
+        Ok(rustls::client::ServerCertVerified::assertion())
+    }
 }
 
 #[async_trait]
-impl Signaller for NativeSignaller {
-    async fn new(mut attempts: Option<u16>, room_url: &str) -> Result<Self, SignalingError> {
+impl SignallerBuilder for NativeSignallerBuilder {
+    async fn new_signaller(
+        &self,
+        mut attempts: Option<u16>,
+        room_url: String,
+    ) -> Result<Box<dyn Signaller>, SignalingError> {
+        // Broken headers or TLS configuration won't get any better by retrying
+        let headers = self.headers().map_err(|e| {
+            SignalingError::NegotiationFailed(Box::new(SignalingError::WebSocket(e.into())))
+        })?;
+        let tls_connector = self.tls_connector().map_err(|e| {
+            SignalingError::NegotiationFailed(Box::new(SignalingError::TlsConfig(Box::new(e))))
+        })?;
         let websocket_stream = 'signaling: loop {
-            match connect_async(room_url).await.map_err(SignalingError::from) {
-                Ok((wss, _)) => break wss,
+            let connection = match room_url.as_str().into_client_request() {
+                Ok(mut request) => {
+                    for (name, value) in &headers {
+                        request.headers_mut().append(name, value.clone());
+                    }
+                    connect(request, &tls_connector)
+                        .await
+                        .map_err(SignalingError::from)
+                }
+                Err(e) => Err(SignalingError::from(e)),
+            };
+            match connection {
+                Ok(wss) => break wss,
                 Err(e) => {
                     if let Some(attempts) = attempts.as_mut() {
                         if *attempts <= 1 {
@@ -70,9 +304,12 @@ impl Signaller for NativeSignaller {
                 }
             };
         };
-        Ok(Self { websocket_stream })
+        Ok(Box::new(NativeSignaller { websocket_stream }))
     }
+}
 
+#[async_trait]
+impl<S: AsyncRead + AsyncWrite + Unpin + Send + 'static> Signaller for NativeSignaller<S> {
     async fn send(&mut self, request: String) -> Result<(), SignalingError> {
         self.websocket_stream
             .send(Message::Text(request))
@@ -81,19 +318,24 @@ impl Signaller for NativeSignaller {
     }
 
     async fn next_message(&mut self) -> Result<String, SignalingError> {
-        match self.websocket_stream.next().await {
-            Some(Ok(Message::Text(message))) => Ok(message),
-            Some(Ok(_)) => Err(SignalingError::UnknownFormat),
-            Some(Err(err)) => Err(SignalingError::from(err)),
-            None => Err(SignalingError::StreamExhausted),
+        loop {
+            match self.websocket_stream.next().await {
+                Some(Ok(Message::Text(message))) => return Ok(message),
+                // Pings are answered by tungstenite itself
+                Some(Ok(Message::Ping(_) | Message::Pong(_))) => {}
+                Some(Ok(_)) => return Err(SignalingError::UnknownFormat),
+                Some(Err(err)) => return Err(SignalingError::from(err)),
+                None => return Err(SignalingError::StreamExhausted),
+            }
         }
     }
 }
 
+#[derive(Default)]
 pub(crate) struct NativeMessenger;
 
-impl PeerDataSender for UnboundedSender<Packet> {
-    fn send(&mut self, packet: Packet) -> Result<(), PacketSendError> {
+impl PeerDataSender for UnboundedSender<QueuedPacket> {
+    fn send(&mut self, packet: QueuedPacket) -> Result<(), PacketSendError> {
         self.unbounded_send(packet)
             .map_err(|source| PacketSendError {
                 source: TrySendError::into_send_error(source),
@@ -103,31 +345,33 @@ impl PeerDataSender for UnboundedSender<Packet> {
 
 #[async_trait]
 impl Messenger for NativeMessenger {
-    type DataChannel = UnboundedSender<Packet>;
+    type DataChannel = UnboundedSender<QueuedPacket>;
     type HandshakeMeta = (
-        Vec<UnboundedReceiver<Packet>>,
+        Vec<UnboundedReceiver<QueuedPacket>>,
         Vec<Arc<RTCDataChannel>>,
         Pin<Box<dyn FusedFuture<Output = Result<(), webrtc::Error>> + Send>>,
         Receiver<()>,
+        Arc<RTCPeerConnection>,
     );
 
     async fn offer_handshake(
+        &self,
         signal_peer: SignalPeer,
         mut peer_signal_rx: UnboundedReceiver<PeerSignal>,
         messages_from_peers_tx: Vec<UnboundedSender<(PeerId, Packet)>>,
-        ice_server_config: &RtcIceServerConfig,
+        ice_config: &IceConfig,
         channel_configs: &[ChannelConfig],
     ) -> HandshakeResult<Self::DataChannel, Self::HandshakeMeta> {
-        async {
+        on_tokio(async {
             let (to_peer_message_tx, to_peer_message_rx) =
                 new_senders_and_receivers(channel_configs);
             let (peer_disconnected_tx, peer_disconnected_rx) = futures_channel::mpsc::channel(1);
 
             debug!("making offer");
-            let (connection, trickle) =
-                create_rtc_peer_connection(signal_peer.clone(), ice_server_config)
-                    .await
-                    .unwrap();
+            let (connection, trickle) = create_rtc_peer_connection(signal_peer.clone(), ice_config)
+                .await
+                .unwrap();
+            let guard = HandshakeGuard::new(&connection);
 
             let (data_channel_ready_txs, data_channels_ready_fut) =
                 create_data_channels_ready_fut(channel_configs);
@@ -164,6 +408,9 @@ impl Messenger for NativeMessenger {
                     PeerSignal::IceCandidate(_) => {
                         warn!("Got an unexpected IceCandidate, while waiting for Answer. Ignoring.")
                     }
+                    PeerSignal::Metadata(_) => {
+                        warn!("Got unexpected Metadata, while waiting for Answer. Ignoring.")
+                    }
                 };
             };
 
@@ -180,6 +427,7 @@ impl Messenger for NativeMessenger {
                 data_channels_ready_fut,
             )
             .await;
+            guard.complete();
 
             HandshakeResult::<Self::DataChannel, Self::HandshakeMeta> {
                 peer_id: signal_peer.id,
@@ -189,30 +437,31 @@ impl Messenger for NativeMessenger {
                     data_channels,
                     trickle_fut,
                     peer_disconnected_rx,
+                    connection,
                 ),
             }
-        }
-        .compat() // Required to run tokio futures with other async executors
+        })
         .await
     }
 
     async fn accept_handshake(
+        &self,
         signal_peer: SignalPeer,
         mut peer_signal_rx: UnboundedReceiver<PeerSignal>,
         messages_from_peers_tx: Vec<UnboundedSender<(PeerId, Packet)>>,
-        ice_server_config: &RtcIceServerConfig,
+        ice_config: &IceConfig,
         channel_configs: &[ChannelConfig],
     ) -> HandshakeResult<Self::DataChannel, Self::HandshakeMeta> {
-        async {
+        on_tokio(async {
             let (to_peer_message_tx, to_peer_message_rx) =
                 new_senders_and_receivers(channel_configs);
             let (peer_disconnected_tx, peer_disconnected_rx) = futures_channel::mpsc::channel(1);
 
             debug!("handshake_accept");
-            let (connection, trickle) =
-                create_rtc_peer_connection(signal_peer.clone(), ice_server_config)
-                    .await
-                    .unwrap();
+            let (connection, trickle) = create_rtc_peer_connection(signal_peer.clone(), ice_config)
+                .await
+                .unwrap();
+            let guard = HandshakeGuard::new(&connection);
 
             let (data_channel_ready_txs, data_channels_ready_fut) =
                 create_data_channels_ready_fut(channel_configs);
@@ -255,6 +504,7 @@ impl Messenger for NativeMessenger {
                 data_channels_ready_fut,
             )
             .await;
+            guard.complete();
 
             HandshakeResult::<Self::DataChannel, Self::HandshakeMeta> {
                 peer_id: signal_peer.id,
@@ -264,17 +514,27 @@ impl Messenger for NativeMessenger {
                     data_channels,
                     trickle_fut,
                     peer_disconnected_rx,
+                    connection,
                 ),
             }
-        }
-        .compat() // Required to run tokio futures with other async executors
+        })
         .await
     }
 
-    async fn peer_loop(peer_uuid: PeerId, handshake_meta: Self::HandshakeMeta) -> PeerId {
-        async {
-            let (mut to_peer_message_rx, data_channels, mut trickle_fut, mut peer_disconnected) =
-                handshake_meta;
+    async fn peer_loop(
+        peer_uuid: PeerId,
+        handshake_meta: Self::HandshakeMeta,
+        stats_interval: Option<Duration>,
+        peer_stats_tx: UnboundedSender<(PeerId, PeerStats)>,
+    ) -> PeerId {
+        on_tokio(async {
+            let (
+                mut to_peer_message_rx,
+                data_channels,
+                mut trickle_fut,
+                mut peer_disconnected,
+                connection,
+            ) = handshake_meta;
 
             assert_eq!(
                 data_channels.len(),
@@ -282,21 +542,37 @@ impl Messenger for NativeMessenger {
                 "amount of data channels and receivers differ"
             );
 
+            let mut buffer_drained_rx = Vec::with_capacity(data_channels.len());
+            for data_channel in &data_channels {
+                buffer_drained_rx.push(on_buffer_drained(data_channel).await);
+            }
+
             let mut message_loop_futs: FuturesUnordered<_> = data_channels
                 .iter()
                 .zip(to_peer_message_rx.iter_mut())
-                .map(|(data_channel, rx)| async move {
+                .zip(buffer_drained_rx)
+                .map(|((data_channel, rx), mut buffer_drained)| async move {
                     while let Some(message) = rx.next().await {
-                        trace!("sending packet {message:?}");
-                        let message = message.clone();
-                        let message = Bytes::from(message);
-                        if let Err(e) = data_channel.send(&message).await {
+                        // Keep the packet in the send queue until the data channel has room
+                        while data_channel.buffered_amount().await > MAX_BUFFERED_AMOUNT {
+                            if buffer_drained.next().await.is_none() {
+                                break;
+                            }
+                        }
+                        trace!("sending packet {:?}", message.packet);
+                        if let Err(e) = data_channel.send(&message.packet).await {
                             error!("error sending to data channel: {e:?}")
                         }
+                        // Only now the packet leaves the send queue
+                        drop(message);
                     }
                 })
                 .collect();
 
+            let mut next_stats = stats_timer(stats_interval);
+            // Collected alongside the other futures, so packets keep flowing in the meantime
+            let mut pending_stats = Fuse::terminated();
+
             loop {
                 select! {
                     _ = peer_disconnected.next() => break,
@@ -305,16 +581,102 @@ impl Messenger for NativeMessenger {
                     // TODO: this means that the signaling is down, should return an
                     // error
                     _ = trickle_fut => continue,
+
+                    _ = next_stats => {
+                        pending_stats = collect_stats(&connection, &data_channels).boxed().fuse();
+                    }
+
+                    stats = pending_stats => {
+                        if peer_stats_tx.unbounded_send((peer_uuid, stats)).is_err() {
+                            // should only happen if the socket is dropped
+                            warn!("failed to report stats for peer {peer_uuid}");
+                        }
+                        next_stats = stats_timer(stats_interval);
+                    }
                 }
             }
 
+            if let Err(e) = connection.close().await {
+                warn!("failed to close connection to peer {peer_uuid}: {e:?}");
+            }
             peer_uuid
-        }
-        .compat() // Required to run tokio futures with other async executors
+        })
         .await
     }
 }
 
+/// Notifies the receiver whenever the buffered amount of the data channel drops to
+/// [`BUFFERED_AMOUNT_LOW_THRESHOLD`]
+async fn on_buffer_drained(data_channel: &RTCDataChannel) -> Receiver<()> {
+    let (mut drained_tx, drained_rx) = futures_channel::mpsc::channel(1);
+    data_channel
+        .set_buffered_amount_low_threshold(BUFFERED_AMOUNT_LOW_THRESHOLD)
+        .await;
+    data_channel
+        .on_buffered_amount_low(Box::new(move || {
+            // A notification that is already pending will do
+            _ = drained_tx.try_send(());
+            Box::pin(async {})
+        }))
+        .await;
+    drained_rx
+}
+
+async fn collect_stats(
+    connection: &RTCPeerConnection,
+    data_channels: &[Arc<RTCDataChannel>],
+) -> PeerStats {
+    let report = connection.get_stats().await;
+
+    let mut channels = vec![ChannelStats::default(); data_channels.len()];
+    for (stats, data_channel) in channels.iter_mut().zip(data_channels) {
+        stats.buffered_amount = data_channel.buffered_amount().await as u64;
+    }
+
+    let mut selected_pair = None;
+    for stats in report.reports.values() {
+        match stats {
+            StatsReportType::CandidatePair(pair) if pair.nominated => selected_pair = Some(pair),
+            StatsReportType::DataChannel(data_channel) => {
+                // Channels are negotiated with their index as id
+                let Some(stats) = channels.get_mut(data_channel.data_channel_identifier as usize)
+                else {
+                    continue;
+                };
+                stats.bytes_sent = data_channel.bytes_sent as u64;
+                stats.bytes_received = data_channel.bytes_received as u64;
+                stats.packets_sent = data_channel.messages_sent as u64;
+                stats.packets_received = data_channel.messages_received as u64;
+            }
+            _ => {}
+        }
+    }
+
+    let candidate_type = |id: &str| match report.reports.get(id) {
+        Some(
+            StatsReportType::LocalCandidate(candidate)
+            | StatsReportType::RemoteCandidate(candidate),
+        ) => match candidate.candidate_type {
+            CandidateType::Host => Some(IceCandidateType::Host),
+            CandidateType::ServerReflexive => Some(IceCandidateType::ServerReflexive),
+            CandidateType::PeerReflexive => Some(IceCandidateType::PeerReflexive),
+            CandidateType::Relay => Some(IceCandidateType::Relay),
+            CandidateType::Unspecified => None,
+        },
+        _ => None,
+    };
+
+    PeerStats {
+        round_trip_time: selected_pair
+            .and_then(|pair| Duration::try_from_secs_f64(pair.current_round_trip_time).ok()),
+        local_candidate_type: selected_pair
+            .and_then(|pair| candidate_type(&pair.local_candidate_id)),
+        remote_candidate_type: selected_pair
+            .and_then(|pair| candidate_type(&pair.remote_candidate_id)),
+        channels,
+    }
+}
+
 async fn complete_handshake(
     trickle: Arc<CandidateTrickle>,
     connection: &Arc<RTCPeerConnection>,
@@ -418,6 +780,9 @@ impl CandidateTrickle {
                 PeerSignal::Answer(_) => {
                     warn!("Got an unexpected Answer, while waiting for IceCandidate. Ignoring.")
                 }
+                PeerSignal::Metadata(_) => {
+                    warn!("Got unexpected Metadata, while waiting for IceCandidate. Ignoring.")
+                }
             }
         }
 
@@ -427,12 +792,14 @@ impl CandidateTrickle {
 
 async fn create_rtc_peer_connection(
     signal_peer: SignalPeer,
-    ice_server_config: &RtcIceServerConfig,
+    ice_config: &IceConfig,
 ) -> Result<(Arc<RTCPeerConnection>, Arc<CandidateTrickle>), Box<dyn std::error::Error>> {
     let api = APIBuilder::new().build();
 
-    let config = RTCConfiguration {
-        ice_servers: vec![RTCIceServer {
+    let ice_servers = ice_config
+        .servers
+        .iter()
+        .map(|ice_server_config| RTCIceServer {
             urls: ice_server_config.urls.clone(),
             username: ice_server_config.username.clone().unwrap_or_default(),
             credential: ice_server_config.credential.clone().unwrap_or_default(),
@@ -454,7 +821,15 @@ async fn create_rtc_peer_connection(
             } else {
                 RTCIceCredentialType::Unspecified
             },
-        }],
+        })
+        .collect();
+
+    let config = RTCConfiguration {
+        ice_servers,
+        ice_transport_policy: match ice_config.transport_policy {
+            RtcIceTransportPolicy::All => RTCIceTransportPolicy::All,
+            RtcIceTransportPolicy::Relay => RTCIceTransportPolicy::Relay,
+        },
         ..Default::default()
     };
 
@@ -555,15 +930,142 @@ async fn create_data_channel(
         Box::pin(async move {})
     }));
 
+    let mut reassembler = channel_config.reassembler();
     channel.on_message(Box::new(move |message| {
-        let packet = (*message.data).into();
-        trace!("data channel message received: {packet:?}");
-        if let Err(e) = from_peer_message_tx.unbounded_send((peer_id, packet)) {
-            // should only happen if the socket is dropped, or we are out of memory
-            warn!("failed to notify about data channel message: {e:?}");
+        let packet = match reassembler.as_mut() {
+            // Only complete once the last fragment arrived
+            Some(reassembler) => reassembler.push(&message.data),
+            None => Some((*message.data).into()),
+        };
+        if let Some(packet) = packet {
+            trace!("data channel message received: {packet:?}");
+            if let Err(e) = from_peer_message_tx.unbounded_send((peer_id, packet)) {
+                // should only happen if the socket is dropped, or we are out of memory
+                warn!("failed to notify about data channel message: {e:?}");
+            }
         }
         Box::pin(async move {})
     }));
 
     channel
 }
+
+// The test servers run on async-std
+#[cfg(all(test, feature = "async-std"))]
+mod test {
+    use super::NativeSignallerBuilder;
+    use crate::{SignalingError, SignalingTlsConfig, SignallerBuilder};
+    use async_tungstenite::tungstenite::Message;
+    use futures::{SinkExt, StreamExt};
+    use futures_rustls::TlsAcceptor;
+    use std::sync::Arc;
+
+    fn self_signed() -> rcgen::Certificate {
+        rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap()
+    }
+
+    // Helper to serve a single websocket connection over TLS, greeting the client with a ping and
+    // a message
+    async fn serve(cert: &rcgen::Certificate) -> u16 {
+        let config = rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![rustls::Certificate(cert.serialize_der().unwrap())],
+                rustls::PrivateKey(cert.serialize_private_key_der()),
+            )
+            .unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+        let listener = async_std::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap();
+        let port = listener.local_addr().unwrap().port();
+        async_std::task::spawn(async move {
+            let (tcp, _) = listener.accept().await.unwrap();
+            let Ok(tls) = acceptor.accept(tcp).await else {
+                return;
+            };
+            let mut websocket = async_tungstenite::accept_async(tls).await.unwrap();
+            websocket.send(Message::Ping(vec![1])).await.unwrap();
+            websocket.send(Message::Text("hello".into())).await.unwrap();
+            // Keep the connection open until the client is done
+            while websocket.next().await.is_some() {}
+        });
+        port
+    }
+
+    async fn connect(tls: SignalingTlsConfig, port: u16) -> Result<String, SignalingError> {
+        let builder = NativeSignallerBuilder {
+            headers: vec![],
+            tls: Some(tls),
+        };
+        let room_url = format!("wss://localhost:{port}/room");
+        let mut signaller = builder.new_signaller(Some(1), room_url).await?;
+        signaller.next_message().await
+    }
+
+    #[futures_test::test]
+    async fn custom_root_certificates_are_trusted() {
+        let cert = self_signed();
+        let port = serve(&cert).await;
+        let tls = SignalingTlsConfig {
+            root_certificates: vec![cert.serialize_der().unwrap()],
+            web_pki_roots: false,
+            ..Default::default()
+        };
+
+        assert_eq!(connect(tls, port).await.unwrap(), "hello");
+    }
+
+    #[futures_test::test]
+    async fn untrusted_certificates_are_rejected() {
+        let port = serve(&self_signed()).await;
+        let tls = SignalingTlsConfig {
+            root_certificates: vec![self_signed().serialize_der().unwrap()],
+            ..Default::default()
+        };
+
+        let result = connect(tls, port).await;
+        assert!(matches!(result, Err(SignalingError::NegotiationFailed(_))));
+    }
+
+    #[futures_test::test]
+    async fn any_certificate_is_accepted_when_asked() {
+        let port = serve(&self_signed()).await;
+        let tls = SignalingTlsConfig {
+            danger_accept_invalid_certificates: true,
+            ..Default::default()
+        };
+
+        assert_eq!(connect(tls, port).await.unwrap(), "hello");
+    }
+
+    #[futures_test::test]
+    async fn invalid_root_certificate_is_a_tls_config_error() {
+        let tls = SignalingTlsConfig {
+            root_certificates: vec![b"not a certificate".to_vec()],
+            ..Default::default()
+        };
+
+        // Fails before connecting, so the port doesn't matter
+        let result = connect(tls, 1).await;
+        let Err(SignalingError::NegotiationFailed(error)) = result else {
+            panic!("expected a failed negotiation, got {result:?}");
+        };
+        assert!(matches!(*error, SignalingError::TlsConfig(_)));
+    }
+
+    #[futures_test::test]
+    async fn invalid_headers_are_not_retried() {
+        let builder = NativeSignallerBuilder {
+            headers: vec![("bad header".to_string(), "value".to_string())],
+            tls: None,
+        };
+
+        // Retrying indefinitely would never return
+        let result = builder
+            .new_signaller(None, "ws://localhost:1/room".into())
+            .await;
+        assert!(matches!(result, Err(SignalingError::NegotiationFailed(_))));
+    }
+}
@@ -0,0 +1,141 @@
+use crate::webrtc_socket::framing;
+use bytes::Bytes;
+use futures::task::AtomicWaker;
+use matchbox_protocol::PeerId;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    task::Waker,
+};
+
+/// The peers whose data channels are open, kept up to date by the message loop so that channels
+/// know who to broadcast to
+#[derive(Debug, Default, Clone)]
+pub(crate) struct ConnectedPeers(Arc<Mutex<HashSet<PeerId>>>);
+
+impl ConnectedPeers {
+    pub(crate) fn insert(&self, peer: PeerId) {
+        self.0.lock().unwrap().insert(peer);
+    }
+
+    pub(crate) fn remove(&self, peer: PeerId) {
+        self.0.lock().unwrap().remove(&peer);
+    }
+
+    pub(crate) fn to_vec(&self) -> Vec<PeerId> {
+        self.0.lock().unwrap().iter().copied().collect()
+    }
+}
+
+/// Keeps count of the packets queued for each peer on a channel
+#[derive(Debug, Default)]
+pub(crate) struct SendQueue {
+    /// Maximum number of packets queued per peer, `None` for no limit
+    capacity: Option<usize>,
+    queued: Mutex<HashMap<PeerId, usize>>,
+    /// Woken whenever a packet leaves the queue
+    popped: AtomicWaker,
+}
+
+impl SendQueue {
+    pub(crate) fn new(capacity: Option<usize>) -> Arc<Self> {
+        Arc::new(Self {
+            capacity,
+            queued: Default::default(),
+            popped: Default::default(),
+        })
+    }
+
+    /// The number of packets queued for the given peer
+    pub(crate) fn len(&self, peer: PeerId) -> usize {
+        let queued = self.queued.lock().unwrap();
+        queued.get(&peer).copied().unwrap_or_default()
+    }
+
+    /// Queues a packet for the given peer, or hands it back if the peer's queue is full
+    pub(crate) fn push(
+        self: &Arc<Self>,
+        packet: Bytes,
+        peer: PeerId,
+    ) -> Result<QueuedPacket, Bytes> {
+        let mut queued = self.queued.lock().unwrap();
+        let count = queued.entry(peer).or_default();
+        if self.capacity.is_some_and(|capacity| *count >= capacity) {
+            return Err(packet);
+        }
+        *count += 1;
+        Ok(QueuedPacket {
+            packet,
+            peer,
+            queue: Some(Arc::clone(self)),
+        })
+    }
+
+    /// Registers a task to wake the next time a packet leaves the queue. Only the most recently
+    /// registered task is woken.
+    pub(crate) fn register(&self, waker: &Waker) {
+        self.popped.register(waker);
+    }
+
+    fn pop(&self, peer: PeerId) {
+        let mut queued = self.queued.lock().unwrap();
+        if let Some(count) = queued.get_mut(&peer) {
+            *count -= 1;
+            if *count == 0 {
+                queued.remove(&peer);
+            }
+        }
+        drop(queued);
+        self.popped.wake();
+    }
+}
+
+/// A packet on its way to a peer.
+///
+/// It counts towards the peer's [`SendQueue`] until it is dropped, i.e. until it has been handed
+/// to the data channel or discarded. The packet's buffer is shared with any other peers it is sent
+/// to, unless it is split into fragments, which are framed for each peer.
+#[derive(Debug)]
+pub(crate) struct QueuedPacket {
+    pub(crate) packet: Bytes,
+    peer: PeerId,
+    queue: Option<Arc<SendQueue>>,
+}
+
+impl QueuedPacket {
+    /// Returns a copy of the packet that doesn't count towards the send queue.
+    pub(crate) fn duplicate(&self) -> QueuedPacket {
+        QueuedPacket {
+            packet: self.packet.clone(),
+            peer: self.peer,
+            queue: None,
+        }
+    }
+
+    /// Splits the packet into framed fragments, see [`framing::fragment`].
+    ///
+    /// Only the last fragment counts towards the send queue, so the packet stays queued until
+    /// all of its fragments have been handed to the data channel.
+    pub(crate) fn into_fragments(mut self, fragment_size: usize) -> Vec<QueuedPacket> {
+        let queue = self.queue.take();
+        let fragments = framing::fragment(&self.packet, fragment_size);
+        let last = fragments.len() - 1;
+        fragments
+            .into_iter()
+            .enumerate()
+            .map(|(i, packet)| QueuedPacket {
+                packet: Bytes::from(packet),
+                peer: self.peer,
+                queue: if i == last { queue.clone() } else { None },
+            })
+            .collect()
+    }
+}
+
+impl Drop for QueuedPacket {
+    fn drop(&mut self) {
+        if let Some(queue) = &self.queue {
+            queue.pop(self.peer);
+        }
+    }
+}
@@ -1,16 +1,35 @@
-use super::error::{ChannelError, SignalingError};
+use super::{
+    error::{ChannelError, SendError, SignalingError},
+    framing::Reassembler,
+    send_queue::{ConnectedPeers, QueuedPacket, SendQueue},
+};
+#[cfg(all(feature = "loopback", not(target_arch = "wasm32")))]
+use crate::webrtc_socket::loopback::{LoopbackMessenger, LoopbackNetwork};
 use crate::{
     webrtc_socket::{
-        message_loop, signaling_loop, MessageLoopFuture, Packet, PeerRequest, SignalEvent,
-        UseMessenger, UseSignaller,
+        message_loop, signaling_loop, with_query_param, MessageLoopFuture, Messenger,
+        NetworkConditioner, Packet, PeerRequest, PeerStats, SignalingEvent, SignallerBuilder,
+        UseMessenger, UseSignallerBuilder,
     },
     Error,
 };
-use futures::{future::Fuse, select, Future, FutureExt, StreamExt};
-use futures_channel::mpsc::{SendError, TrySendError, UnboundedReceiver, UnboundedSender};
-use log::{debug, error};
-use matchbox_protocol::PeerId;
-use std::{collections::HashMap, marker::PhantomData, pin::Pin, time::Duration};
+use bytes::Bytes;
+use futures::{
+    future::{Fuse, FusedFuture},
+    select, Future, FutureExt, Sink, Stream, StreamExt,
+};
+use futures_channel::mpsc::{UnboundedReceiver, UnboundedSender};
+use futures_timer::Delay;
+use log::{debug, error, warn};
+use matchbox_protocol::{PeerId, AUTH_TOKEN_QUERY_PARAM};
+use std::{
+    collections::HashMap,
+    marker::PhantomData,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
 
 /// Configuration options for an ICE server connection.
 /// See also: <https://developer.mozilla.org/en-US/docs/Web/API/RTCIceServer#example>
@@ -28,6 +47,39 @@ pub struct RtcIceServerConfig {
     pub credential: Option<String>,
 }
 
+/// Which ICE candidates may be used to connect to peers
+/// See also: <https://developer.mozilla.org/en-US/docs/Web/API/RTCPeerConnection/RTCPeerConnection#icetransportpolicy>
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RtcIceTransportPolicy {
+    /// Any candidate may be used
+    #[default]
+    All,
+    /// Only relay candidates, i.e. those provided by a TURN server, may be used. This keeps the
+    /// peers' IP addresses hidden from each other.
+    Relay,
+}
+
+/// The ICE configuration used for every peer connection
+#[derive(Debug, Clone)]
+pub(crate) struct IceConfig {
+    /// The ICE servers to gather candidates from
+    pub(crate) servers: Vec<RtcIceServerConfig>,
+    /// Which of the gathered candidates may be used
+    pub(crate) transport_policy: RtcIceTransportPolicy,
+}
+
+impl Default for IceConfig {
+    fn default() -> Self {
+        Self {
+            servers: vec![RtcIceServerConfig::default()],
+            transport_policy: RtcIceTransportPolicy::default(),
+        }
+    }
+}
+
+/// Default maximum size of a packet reassembled from fragments
+const DEFAULT_MAX_REASSEMBLED_SIZE: usize = 16 * 1024 * 1024;
+
 /// Configuration options for a data channel
 /// See also: <https://developer.mozilla.org/en-US/docs/Web/API/RTCDataChannel>
 #[derive(Debug, Clone)]
@@ -40,6 +92,12 @@ pub struct ChannelConfig {
     pub max_retransmits: Option<u16>,
 
     pub max_packet_life_time: Option<u16>,
+    /// See [`ChannelConfig::send_queue_capacity`]
+    pub(crate) send_queue_capacity: Option<usize>,
+    /// See [`ChannelConfig::fragment_size`]
+    pub(crate) fragment_size: Option<usize>,
+    /// See [`ChannelConfig::max_reassembled_size`]
+    pub(crate) max_reassembled_size: usize,
 }
 
 impl ChannelConfig {
@@ -50,6 +108,9 @@ impl ChannelConfig {
             ordered: false,
             max_retransmits: Some(1),
             max_packet_life_time: Some(0),
+            send_queue_capacity: None,
+            fragment_size: None,
+            max_reassembled_size: DEFAULT_MAX_REASSEMBLED_SIZE,
         }
     }
 
@@ -60,10 +121,205 @@ impl ChannelConfig {
             ordered: true,
             max_retransmits: None,
             max_packet_life_time: None,
+            send_queue_capacity: None,
+            fragment_size: None,
+            max_reassembled_size: DEFAULT_MAX_REASSEMBLED_SIZE,
+        }
+    }
+
+    /// Sets the maximum number of packets queued for a single peer before
+    /// [`WebRtcChannel::try_send`] fails with [`SendError::WouldBlock`], `None` for no limit.
+    ///
+    /// On native, packets stay queued while the data channel's buffer is full.
+    ///
+    /// See also: [`WebRtcChannel::queued_packets`]
+    ///
+    /// The default is `None`.
+    pub fn send_queue_capacity(mut self, capacity: Option<usize>) -> Self {
+        self.send_queue_capacity = capacity;
+        self
+    }
+
+    /// Splits packets larger than this many bytes into fragments, which are reassembled before
+    /// they are returned by [`WebRtcChannel::receive`], `None` to send packets as they are.
+    ///
+    /// Only supported on reliable channels, see [`ChannelConfig::reliable`]. Every peer must use
+    /// the same setting for the channel, as each message gets a small framing header.
+    ///
+    /// The default is `None`.
+    pub fn fragment_size(mut self, fragment_size: Option<usize>) -> Self {
+        self.fragment_size = fragment_size;
+        self
+    }
+
+    /// Sets the maximum size in bytes of a packet reassembled from fragments, see
+    /// [`ChannelConfig::fragment_size`]. Larger packets are discarded, so a peer can't make us
+    /// buffer without bounds.
+    ///
+    /// The default is 16 MiB.
+    pub fn max_reassembled_size(mut self, max_size: usize) -> Self {
+        self.max_reassembled_size = max_size;
+        self
+    }
+
+    /// Whether messages on the channel are guaranteed to arrive, in order
+    pub(crate) fn is_reliable(&self) -> bool {
+        self.ordered && self.max_retransmits.is_none() && self.max_packet_life_time.is_none()
+    }
+
+    /// The fragment size, if fragmentation is enabled on a channel that supports it
+    pub(crate) fn fragmentation(&self) -> Option<usize> {
+        self.fragment_size.filter(|_| self.is_reliable())
+    }
+
+    /// Reassembles fragmented packets received on the channel, if fragmentation is enabled
+    pub(crate) fn reassembler(&self) -> Option<Reassembler> {
+        self.fragmentation()
+            .map(|_| Reassembler::new(self.max_reassembled_size))
+    }
+}
+
+/// Policy for re-establishing a lost connection to the signaling server.
+///
+/// Peer connections that are already established are kept alive while the socket is
+/// reconnecting.
+#[derive(Debug, Clone)]
+pub struct SignalingReconnectPolicy {
+    /// Maximum number of reconnection attempts before giving up, `None` to retry indefinitely
+    pub max_attempts: Option<u16>,
+    /// Delay before the first reconnection attempt
+    pub initial_delay: Duration,
+    /// Upper bound for the delay between two reconnection attempts
+    pub max_delay: Duration,
+    /// Factor the delay is multiplied with after every failed attempt
+    pub multiplier: f64,
+}
+
+impl SignalingReconnectPolicy {
+    /// The delay to wait before the reconnection attempt with the given (zero-based) index
+    pub(crate) fn delay(&self, attempt: u16) -> Duration {
+        let secs = self.initial_delay.as_secs_f64() * self.multiplier.powi(attempt.into());
+        Duration::try_from_secs_f64(secs).map_or(self.max_delay, |delay| delay.min(self.max_delay))
+    }
+}
+
+impl Default for SignalingReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: Some(5),
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+            multiplier: 2.0,
+        }
+    }
+}
+
+/// A peer the socket is about to connect to, see [`WebRtcSocketBuilder::peer_filter`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeerCandidate {
+    /// The id of the peer
+    pub id: PeerId,
+    /// Whether the peer offered to connect to us, instead of us offering to connect to it
+    pub incoming: bool,
+    /// The metadata the peer sent with its offer, see [`WebRtcSocketBuilder::metadata`]
+    ///
+    /// Always `None` if the connection isn't incoming, as only the offering peer sends metadata.
+    pub metadata: Option<serde_json::Value>,
+    /// The number of peers we're already connected or connecting to
+    pub peers: usize,
+}
+
+type PeerFilter = Arc<dyn Fn(&PeerCandidate) -> bool + Send + Sync>;
+
+/// Decides which peers the socket connects to
+#[derive(Clone, Default)]
+pub(crate) struct PeerPolicy {
+    /// Returns whether to connect to a peer, `None` to connect to every peer
+    pub(crate) filter: Option<PeerFilter>,
+    /// The maximum number of peers to be connected or connecting to at once
+    pub(crate) max_peers: Option<usize>,
+    /// Sent to the peers we offer to connect to
+    pub(crate) metadata: Option<serde_json::Value>,
+}
+
+impl PeerPolicy {
+    /// Whether the socket is already connected or connecting to as many peers as it may be
+    pub(crate) fn is_full(&self, peers: usize) -> bool {
+        self.max_peers.is_some_and(|max| peers >= max)
+    }
+
+    /// Whether the filter allows connecting to the given peer
+    pub(crate) fn accepts(&self, candidate: &PeerCandidate) -> bool {
+        match &self.filter {
+            Some(filter) => filter(candidate),
+            None => true,
+        }
+    }
+}
+
+impl std::fmt::Debug for PeerPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PeerPolicy")
+            .field("filter", &self.filter.as_ref().map(|_| "Fn"))
+            .field("max_peers", &self.max_peers)
+            .field("metadata", &self.metadata)
+            .finish()
+    }
+}
+
+/// TLS settings for the connection to a `wss://` signaling server
+///
+/// See also: [`WebRtcSocketBuilder::signaling_tls`]
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Clone)]
+pub struct SignalingTlsConfig {
+    /// DER encoded CA certificates to trust, e.g. the CA of a staging environment
+    pub root_certificates: Vec<Vec<u8>>,
+    /// Whether to trust the usual web PKI root certificates as well. Disable this to only trust
+    /// [`SignalingTlsConfig::root_certificates`], e.g. to pin a CA.
+    pub web_pki_roots: bool,
+    /// A certificate to present to servers that require client authentication
+    pub client_certificate: Option<ClientCertificate>,
+    /// Accepts any server certificate, including self-signed and expired ones, e.g. to connect
+    /// to a local development server.
+    ///
+    /// This makes the connection vulnerable to man-in-the-middle attacks, never enable it in
+    /// production.
+    pub danger_accept_invalid_certificates: bool,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Default for SignalingTlsConfig {
+    fn default() -> Self {
+        Self {
+            root_certificates: Vec::new(),
+            web_pki_roots: true,
+            client_certificate: None,
+            danger_accept_invalid_certificates: false,
         }
     }
 }
 
+/// A client certificate for the connection to the signaling server, see [`SignalingTlsConfig`]
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone)]
+pub struct ClientCertificate {
+    /// DER encoded certificate chain, starting with the client's own certificate
+    pub chain: Vec<Vec<u8>>,
+    /// DER encoded PKCS#8 or PKCS#1 private key of the client's certificate
+    pub private_key: Vec<u8>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl std::fmt::Debug for ClientCertificate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Keep the private key out of logs
+        f.debug_struct("ClientCertificate")
+            .field("chain", &self.chain)
+            .finish_non_exhaustive()
+    }
+}
+
 impl Default for RtcIceServerConfig {
     fn default() -> Self {
         Self {
@@ -116,14 +372,56 @@ pub(crate) struct SocketConfig {
     ///
     /// The last form will pair player in the order they connect.
     pub(crate) room_url: String,
-    /// Configuration for the (single) ICE server
-    pub(crate) ice_server: RtcIceServerConfig,
+    /// Configuration for the ICE servers and transport policy
+    pub(crate) ice: IceConfig,
     /// Configuration for one or multiple reliable or unreliable data channels
     pub(crate) channels: Vec<ChannelConfig>,
     /// The amount of attempts to initiate connection
     pub(crate) attempts: Option<u16>,
+    /// How to reconnect after losing an established signaling connection, `None` to give up
+    pub(crate) reconnect_policy: Option<SignalingReconnectPolicy>,
     /// Interval at which to send empty requests to the signaling server
     pub(crate) keep_alive_interval: Option<Duration>,
+    /// How long to wait for a handshake with a peer to complete, `None` to wait indefinitely
+    pub(crate) handshake_timeout: Option<Duration>,
+    /// Interval at which to collect connection statistics for each peer
+    pub(crate) stats_interval: Option<Duration>,
+    /// Whether a malformed message from the signaling server is an error instead of being ignored
+    pub(crate) strict_signaling: bool,
+    /// Creates the connections to the signaling server, `None` for a websocket connection
+    pub(crate) signaller_builder: Option<Arc<dyn SignallerBuilder>>,
+    /// Presented to the signaling server as a query parameter
+    pub(crate) signaling_token: Option<String>,
+    /// Extra headers for the websocket request to the signaling server
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) signaling_headers: Vec<(String, String)>,
+    /// TLS settings for the websocket connection, `None` for the defaults
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) signaling_tls: Option<SignalingTlsConfig>,
+    /// Simulates network conditions on outgoing packets, `None` to send them as is
+    pub(crate) conditioner: Option<NetworkConditioner>,
+    /// Which peers to connect to
+    pub(crate) peer_policy: PeerPolicy,
+    /// The loopback network to connect to peers through instead of WebRTC
+    #[cfg(all(feature = "loopback", not(target_arch = "wasm32")))]
+    pub(crate) loopback: Option<LoopbackNetwork>,
+}
+
+impl SocketConfig {
+    /// The custom signaller builder, or the default one for the current platform
+    fn signaller_builder(&self) -> Arc<dyn SignallerBuilder> {
+        if let Some(builder) = &self.signaller_builder {
+            return Arc::clone(builder);
+        }
+        #[cfg(target_arch = "wasm32")]
+        let builder = UseSignallerBuilder::default();
+        #[cfg(not(target_arch = "wasm32"))]
+        let builder = UseSignallerBuilder {
+            headers: self.signaling_headers.clone(),
+            tls: self.signaling_tls.clone(),
+        };
+        Arc::new(builder)
+    }
 }
 
 /// Builder for [`WebRtcSocket`]s.
@@ -147,18 +445,61 @@ impl WebRtcSocketBuilder {
         Self {
             config: SocketConfig {
                 room_url: room_url.into(),
-                ice_server: RtcIceServerConfig::default(),
+                ice: IceConfig::default(),
                 channels: Vec::default(),
                 attempts: Some(3),
+                reconnect_policy: Some(SignalingReconnectPolicy::default()),
                 keep_alive_interval: Some(Duration::from_secs(10)),
+                handshake_timeout: Some(Duration::from_secs(30)),
+                stats_interval: None,
+                strict_signaling: false,
+                signaller_builder: None,
+                signaling_token: None,
+                #[cfg(not(target_arch = "wasm32"))]
+                signaling_headers: Vec::new(),
+                #[cfg(not(target_arch = "wasm32"))]
+                signaling_tls: None,
+                conditioner: None,
+                peer_policy: PeerPolicy::default(),
+                #[cfg(all(feature = "loopback", not(target_arch = "wasm32")))]
+                loopback: None,
             },
             channel_plurality: PhantomData,
         }
     }
 
-    /// Sets the socket ICE server configuration.
+    /// Sets the socket ICE server configuration, replacing any previously configured ICE
+    /// servers.
     pub fn ice_server(mut self, ice_server: RtcIceServerConfig) -> Self {
-        self.config.ice_server = ice_server;
+        self.config.ice.servers = vec![ice_server];
+        self
+    }
+
+    /// Sets the ICE servers to use, e.g. a STUN server and several TURN servers with their own
+    /// credentials.
+    ///
+    /// The default is a single [`RtcIceServerConfig::default`].
+    pub fn ice_servers(
+        mut self,
+        ice_servers: impl IntoIterator<Item = RtcIceServerConfig>,
+    ) -> Self {
+        self.config.ice.servers = ice_servers.into_iter().collect();
+        self
+    }
+
+    /// Adds an ICE server to the ones already configured.
+    pub fn add_ice_server(mut self, ice_server: RtcIceServerConfig) -> Self {
+        self.config.ice.servers.push(ice_server);
+        self
+    }
+
+    /// Sets which ICE candidates may be used to connect to peers.
+    ///
+    /// [`RtcIceTransportPolicy::Relay`] requires at least one TURN server to be configured.
+    ///
+    /// The default is [`RtcIceTransportPolicy::All`].
+    pub fn ice_transport_policy(mut self, policy: RtcIceTransportPolicy) -> Self {
+        self.config.ice.transport_policy = policy;
         self
     }
 
@@ -171,6 +512,29 @@ impl WebRtcSocketBuilder {
         self
     }
 
+    /// Sets how the socket reconnects if an established connection to the signaling server is
+    /// lost. If `None`, the socket will fail with [`Error::Disconnected`] instead.
+    ///
+    /// While reconnecting, existing peer connections stay open, and the socket emits
+    /// [`SignalingEvent::Lost`] and [`SignalingEvent::Restored`] through
+    /// [`WebRtcSocket::update_signals`].
+    ///
+    /// The default is [`SignalingReconnectPolicy::default`].
+    pub fn signaling_reconnect_policy(mut self, policy: Option<SignalingReconnectPolicy>) -> Self {
+        self.config.reconnect_policy = policy;
+        self
+    }
+
+    /// Sets whether a message from the signaling server that isn't a valid event, e.g. from a
+    /// newer server or injected by a proxy, fails the socket with
+    /// [`SignalingError::MalformedMessage`] instead of being logged and ignored.
+    ///
+    /// The default is `false`.
+    pub fn strict_signaling(mut self, strict: bool) -> Self {
+        self.config.strict_signaling = strict;
+        self
+    }
+
     /// Sets the interval at which to send empty requests to the signaling server.
     ///
     /// Some web services (like e.g. nginx as a reverse proxy) will close idle
@@ -183,6 +547,160 @@ impl WebRtcSocketBuilder {
         self.config.keep_alive_interval = interval;
         self
     }
+
+    /// Sets how long to wait for the data channels to a peer to open, if `None` the socket will
+    /// wait indefinitely.
+    ///
+    /// Peers whose handshake doesn't complete in time, e.g. because none of the ICE candidates
+    /// could connect, are reported as [`PeerState::Failed`].
+    ///
+    /// The default is 30 seconds.
+    pub fn handshake_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.config.handshake_timeout = timeout;
+        self
+    }
+
+    /// Sets the interval at which connection statistics are collected for each peer, if `None`
+    /// no statistics are collected.
+    ///
+    /// See also: [`WebRtcSocket::peer_stats`]
+    ///
+    /// The default is `None`.
+    pub fn stats_interval(mut self, interval: Option<Duration>) -> Self {
+        self.config.stats_interval = interval;
+        self
+    }
+
+    /// Passes all outgoing packets through a [`NetworkConditioner`], to simulate latency,
+    /// jitter, loss, duplication and reordering.
+    ///
+    /// The conditioner can be shared with other sockets and changed while the socket is running.
+    pub fn network_conditioner(mut self, conditioner: &NetworkConditioner) -> Self {
+        self.config.conditioner = Some(conditioner.clone());
+        self
+    }
+
+    /// Sets a predicate deciding whether to connect to a peer, both when the signaling server
+    /// tells us about a new peer and when a peer offers to connect to us.
+    ///
+    /// Peers that are rejected aren't connected to and don't show up as [`PeerState::Connecting`].
+    /// If both sockets reject each other, neither of them starts a handshake.
+    ///
+    /// A rejected offer is ignored rather than declined, as the protocol has no way to decline
+    /// it. The offering socket reports the peer as [`PeerState::Connecting`] until its
+    /// [`WebRtcSocketBuilder::handshake_timeout`] expires, and as [`PeerState::Failed`] after that.
+    /// Signals from a rejected peer are ignored until it leaves the room.
+    ///
+    /// ```
+    /// use matchbox_socket::*;
+    ///
+    /// let (socket, message_loop) = WebRtcSocketBuilder::new("wss://example.invalid/")
+    ///     .metadata(serde_json::json!({ "region": "eu" }))
+    ///     .peer_filter(|peer| {
+    ///         peer.metadata
+    ///             .as_ref()
+    ///             .map_or(true, |metadata| metadata["region"] == "eu")
+    ///     })
+    ///     .max_peers(Some(4))
+    ///     .add_channel(ChannelConfig::reliable())
+    ///     .build();
+    /// ```
+    pub fn peer_filter(
+        mut self,
+        filter: impl Fn(&PeerCandidate) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.config.peer_policy.filter = Some(Arc::new(filter));
+        self
+    }
+
+    /// Sets the maximum number of peers to be connected or connecting to at once, if `None` the
+    /// socket connects to every peer.
+    ///
+    /// Peers beyond the limit are turned down like peers rejected by
+    /// [`WebRtcSocketBuilder::peer_filter`], except that they aren't remembered: an offer from the
+    /// same peer is accepted once there is room again.
+    ///
+    /// The default is `None`.
+    pub fn max_peers(mut self, max_peers: Option<usize>) -> Self {
+        self.config.peer_policy.max_peers = max_peers;
+        self
+    }
+
+    /// Sets metadata sent to every peer we offer to connect to, e.g. a player name or a region,
+    /// which it can inspect in its [`WebRtcSocketBuilder::peer_filter`] before accepting.
+    pub fn metadata(mut self, metadata: serde_json::Value) -> Self {
+        self.config.peer_policy.metadata = Some(metadata);
+        self
+    }
+
+    /// Sets a custom [`SignallerBuilder`], to reach the signaling server over a transport other
+    /// than the default websocket connection.
+    ///
+    /// The room url is passed to the builder as is, so it doesn't have to be a websocket url.
+    pub fn signaller_builder(mut self, builder: impl SignallerBuilder) -> Self {
+        self.config.signaller_builder = Some(Arc::new(builder));
+        self
+    }
+
+    /// Sets a token to authenticate with the signaling server, e.g. a ticket handed out by a
+    /// matchmaker.
+    ///
+    /// The token is added to the room url as the `token` query parameter, which the server can
+    /// check in its `on_connection_request` callback. This works on all platforms, as opposed to
+    /// [`WebRtcSocketBuilder::signaling_header`], since browsers can't set headers on websocket
+    /// requests.
+    pub fn signaling_token(mut self, token: impl Into<String>) -> Self {
+        self.config.signaling_token = Some(token.into());
+        self
+    }
+
+    /// Adds a header to the websocket request to the signaling server, e.g. an `Authorization`
+    /// header. Not available on wasm, where [`WebRtcSocketBuilder::signaling_token`] can be
+    /// used instead.
+    ///
+    /// Headers are only sent by the default websocket connection, not by a custom
+    /// [`WebRtcSocketBuilder::signaller_builder`]. An invalid header name or value fails the
+    /// connection to the signaling server.
+    ///
+    /// ```
+    /// use matchbox_socket::*;
+    ///
+    /// let (socket, message_loop) = WebRtcSocketBuilder::new("wss://example.invalid/")
+    ///     .signaling_header("Authorization", "Bearer 0123456789")
+    ///     .add_channel(ChannelConfig::reliable())
+    ///     .build();
+    /// ```
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn signaling_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.config
+            .signaling_headers
+            .push((name.into(), value.into()));
+        self
+    }
+
+    /// Sets the TLS settings for a `wss://` signaling server, e.g. to trust a custom CA or to
+    /// authenticate with a client certificate. Not available on wasm, where the browser decides.
+    ///
+    /// Like [`WebRtcSocketBuilder::signaling_header`], this only applies to the default
+    /// websocket connection. An invalid configuration fails the socket with
+    /// [`Error::ConnectionFailed`].
+    ///
+    /// ```
+    /// use matchbox_socket::*;
+    ///
+    /// let (socket, message_loop) = WebRtcSocketBuilder::new("wss://localhost:3536/")
+    ///     .signaling_tls(SignalingTlsConfig {
+    ///         danger_accept_invalid_certificates: true,
+    ///         ..Default::default()
+    ///     })
+    ///     .add_channel(ChannelConfig::reliable())
+    ///     .build();
+    /// ```
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn signaling_tls(mut self, config: SignalingTlsConfig) -> Self {
+        self.config.signaling_tls = Some(config);
+        self
+    }
 }
 
 impl WebRtcSocketBuilder<NoChannels> {
@@ -279,17 +797,35 @@ impl<C: BuildablePlurality> WebRtcSocketBuilder<C> {
             unreachable!();
         }
 
+        for (index, channel) in self.config.channels.iter().enumerate() {
+            if channel.fragment_size.is_some() && channel.fragmentation().is_none() {
+                warn!("ignoring fragment size of channel {index}, as it isn't reliable");
+            }
+        }
+
         let (signal_tx, signal_rx) = futures_channel::mpsc::unbounded();
         let (peer_state_tx, peer_state_rx) = futures_channel::mpsc::unbounded();
+        let (peer_stats_tx, peer_stats_rx) = futures_channel::mpsc::unbounded();
+        let (commands_tx, commands_rx) = futures_channel::mpsc::unbounded();
 
         let (messages_from_peers_tx, messages_from_peers_rx) =
             new_senders_and_receivers(&self.config.channels);
         let (peer_messages_out_tx, peer_messages_out_rx) =
             new_senders_and_receivers(&self.config.channels);
+        let connected_peers = ConnectedPeers::default();
         let channels = messages_from_peers_rx
             .into_iter()
             .zip(peer_messages_out_tx)
-            .map(|(rx, tx)| Some(WebRtcChannel { rx, tx }))
+            .zip(&self.config.channels)
+            .map(|((rx, tx), config)| {
+                Some(WebRtcChannel {
+                    rx,
+                    tx,
+                    queue: SendQueue::new(config.send_queue_capacity),
+                    connected_peers: connected_peers.clone(),
+                    pending_send: None,
+                })
+            })
             .collect();
 
         let (id_tx, id_rx) = futures_channel::oneshot::channel();
@@ -300,7 +836,10 @@ impl<C: BuildablePlurality> WebRtcSocketBuilder<C> {
             peer_messages_out_rx,
             signal_tx,
             peer_state_tx,
+            peer_stats_tx,
             messages_from_peers_tx,
+            connected_peers,
+            commands_rx,
         )
         // Transform the source into a user-error.
         .map(|f| {
@@ -308,9 +847,13 @@ impl<C: BuildablePlurality> WebRtcSocketBuilder<C> {
                 SignalingError::UndeliverableSignal(e) => Error::Disconnected(e.into()),
                 SignalingError::NegotiationFailed(e) => Error::ConnectionFailed(*e),
                 SignalingError::WebSocket(e) => Error::Disconnected(e.into()),
-                SignalingError::UnknownFormat | SignalingError::StreamExhausted => {
-                    unimplemented!("these errors should never be propagated here")
-                }
+                #[cfg(not(target_arch = "wasm32"))]
+                e @ SignalingError::TlsConfig(_) => Error::ConnectionFailed(e),
+                // The connection was closed or broke, and wasn't (or couldn't be) re-established
+                e @ (SignalingError::StreamExhausted
+                | SignalingError::UnknownFormat
+                | SignalingError::Custom(_)
+                | SignalingError::MalformedMessage { .. }) => Error::Disconnected(e),
             })
         });
 
@@ -321,7 +864,10 @@ impl<C: BuildablePlurality> WebRtcSocketBuilder<C> {
                 signal_rx,
                 peer_state_rx,
                 peers: Default::default(),
+                peer_stats_rx,
+                peer_stats: Default::default(),
                 channels,
+                commands_tx,
                 channel_plurality: PhantomData,
             },
             Box::pin(socket_fut),
@@ -329,9 +875,21 @@ impl<C: BuildablePlurality> WebRtcSocketBuilder<C> {
     }
 }
 
+/// The reason a connection to a peer could not be established
+#[derive(Clone, Copy, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum ConnectionFailure {
+    /// The data channels didn't open within the configured handshake timeout
+    ///
+    /// See also: [`WebRtcSocketBuilder::handshake_timeout`]
+    #[error("the handshake did not complete within {0:?}")]
+    HandshakeTimeout(Duration),
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 /// The state of a connection to a peer
 pub enum PeerState {
+    /// We are performing the handshake with the peer, but the data channels are not open yet
+    Connecting,
     /// The peer is connected
     ///
     /// This means all of the following should be true:
@@ -346,13 +904,22 @@ pub enum PeerState {
     /// - Some of the the data channels got disconnected/closed
     /// - The peer left the signaling server
     Disconnected,
+    /// The handshake with the peer didn't succeed, so we never got connected
+    Failed {
+        /// Why the connection could not be established
+        reason: ConnectionFailure,
+    },
 }
 /// Used to send and receive packets on a given WebRTC channel. Must be created as part of a
 /// [`WebRtcSocket`].
 #[derive(Debug)]
 pub struct WebRtcChannel {
-    tx: UnboundedSender<(PeerId, Packet)>,
+    tx: UnboundedSender<(PeerId, QueuedPacket)>,
     rx: UnboundedReceiver<(PeerId, Packet)>,
+    queue: Arc<SendQueue>,
+    connected_peers: ConnectedPeers,
+    /// A packet sent through the [`Sink`] that is waiting for room in the peer's send queue
+    pending_send: Option<(PeerId, Bytes)>,
 }
 
 impl WebRtcChannel {
@@ -368,19 +935,151 @@ impl WebRtcChannel {
     }
 
     /// Try to send a packet to the given peer. An error is propagated if the socket future
-    /// is dropped. `Ok` is not a guarantee of delivery.
+    /// is dropped, or if the peer's send queue is full. `Ok` is not a guarantee of delivery.
     pub fn try_send(&mut self, packet: Packet, peer: PeerId) -> Result<(), SendError> {
-        self.tx
-            .unbounded_send((peer, packet))
-            .map_err(TrySendError::into_send_error)
+        self.send_shared(Bytes::from(packet), peer)
     }
 
     /// Send a packet to the given peer. There is no guarantee of delivery.
     ///
+    /// If the peer's send queue is full, the packet is dropped. Use
+    /// [`WebRtcChannel::try_send`] to handle this case yourself.
+    ///
     /// # Panics
     /// Panics if the socket future is dropped.
     pub fn send(&mut self, packet: Packet, peer: PeerId) {
-        self.try_send(packet, peer).expect("Send failed");
+        match self.try_send(packet, peer) {
+            Err(SendError::WouldBlock) => {
+                warn!("send queue for peer {peer} is full, dropping packet")
+            }
+            result => result.expect("Send failed"),
+        }
+    }
+
+    /// Sends a packet to every peer whose data channels are currently open. There is no
+    /// guarantee of delivery.
+    ///
+    /// Returns the peers the packet could not be queued for, see [`WebRtcChannel::try_send`].
+    ///
+    /// Note: Unlike [`WebRtcSocket::connected_peers`], this doesn't depend on
+    /// [`WebRtcSocket::update_peers`] being called.
+    pub fn broadcast(&mut self, packet: Packet) -> Vec<(PeerId, SendError)> {
+        let peers = self.connected_peers.to_vec();
+        self.send_to_many(packet, peers)
+    }
+
+    /// Sends a packet to each of the given peers. There is no guarantee of delivery.
+    ///
+    /// The packet is shared between the peers instead of being copied for each of them. Channels
+    /// with fragmentation frame the fragments for each peer though, and the loopback backend hands
+    /// each peer its own copy. Returns the peers the packet could not be queued for, see
+    /// [`WebRtcChannel::try_send`].
+    pub fn send_to_many(
+        &mut self,
+        packet: Packet,
+        peers: impl IntoIterator<Item = PeerId>,
+    ) -> Vec<(PeerId, SendError)> {
+        let packet = Bytes::from(packet);
+        peers
+            .into_iter()
+            .filter_map(|peer| {
+                self.send_shared(packet.clone(), peer)
+                    .err()
+                    .map(|err| (peer, err))
+            })
+            .collect()
+    }
+
+    fn send_shared(&mut self, packet: Bytes, peer: PeerId) -> Result<(), SendError> {
+        let packet = self
+            .queue
+            .push(packet, peer)
+            .map_err(|_| SendError::WouldBlock)?;
+        self.tx
+            .unbounded_send((peer, packet))
+            .map_err(|_| SendError::Closed)
+    }
+
+    /// Returns the number of packets sent to the given peer that haven't been handed to the
+    /// underlying data channel yet.
+    ///
+    /// See also: [`ChannelConfig::send_queue_capacity`]
+    pub fn queued_packets(&self, peer: PeerId) -> usize {
+        self.queue.len(peer)
+    }
+}
+
+/// Yields received packets as they arrive, ending when the socket is closed.
+///
+/// Packets yielded by the stream are removed from the channel, just like with
+/// [`WebRtcChannel::receive`].
+impl Stream for WebRtcChannel {
+    type Item = (PeerId, Packet);
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.rx.poll_next_unpin(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.rx.size_hint()
+    }
+}
+
+/// Sends packets like [`WebRtcChannel::try_send`], but waits for room in the peer's send queue
+/// instead of failing with [`SendError::WouldBlock`].
+///
+/// The sink holds on to one packet while the recipient's queue is full, so a full queue blocks
+/// packets sent to other peers through the sink as well. Note that [`WebRtcChannel::send`] shadows
+/// [`SinkExt::send`](futures::SinkExt::send), so the latter has to be called as
+/// `SinkExt::send(&mut channel, (peer, packet))`.
+impl Sink<(PeerId, Packet)> for WebRtcChannel {
+    type Error = SendError;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.as_mut().poll_flush(cx)
+    }
+
+    fn start_send(
+        mut self: Pin<&mut Self>,
+        (peer, packet): (PeerId, Packet),
+    ) -> Result<(), Self::Error> {
+        if self.tx.is_closed() {
+            return Err(SendError::Closed);
+        }
+        debug_assert!(
+            self.pending_send.is_none(),
+            "start_send called before poll_ready"
+        );
+        self.pending_send = Some((peer, Bytes::from(packet)));
+        Ok(())
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if self.tx.is_closed() {
+            self.pending_send = None;
+            return Poll::Ready(Err(SendError::Closed));
+        }
+        let Some((peer, packet)) = self.pending_send.take() else {
+            return Poll::Ready(Ok(()));
+        };
+        // Register before trying, so a packet leaving the queue in between isn't missed
+        self.queue.register(cx.waker());
+        match self.queue.push(packet, peer) {
+            Ok(packet) => Poll::Ready(
+                self.tx
+                    .unbounded_send((peer, packet))
+                    .map_err(|_| SendError::Closed),
+            ),
+            Err(packet) => {
+                self.pending_send = Some((peer, packet));
+                Poll::Pending
+            }
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // The channel is owned by the socket, so closing the sink only flushes it
+        self.poll_flush(cx)
     }
 }
 
@@ -389,10 +1088,13 @@ impl WebRtcChannel {
 pub struct WebRtcSocket<C: ChannelPlurality = SingleChannel> {
     id: once_cell::race::OnceBox<PeerId>,
     id_rx: futures_channel::oneshot::Receiver<PeerId>,
-    signal_rx: futures_channel::mpsc::UnboundedReceiver<SignalEvent>,
+    signal_rx: futures_channel::mpsc::UnboundedReceiver<SignalingEvent>,
     peer_state_rx: futures_channel::mpsc::UnboundedReceiver<(PeerId, PeerState)>,
     peers: HashMap<PeerId, PeerState>,
+    peer_stats_rx: futures_channel::mpsc::UnboundedReceiver<(PeerId, PeerStats)>,
+    peer_stats: HashMap<PeerId, PeerStats>,
     channels: Vec<Option<WebRtcChannel>>,
+    commands_tx: UnboundedSender<SocketCommand>,
     channel_plurality: PhantomData<C>,
 }
 
@@ -438,15 +1140,15 @@ impl WebRtcSocket {
 }
 
 impl<C: ChannelPlurality> WebRtcSocket<C> {
-    /// Handle peers connecting or disconnecting
+    /// Handle peers connecting, disconnecting or failing to connect
     ///
     /// Constructed using [`WebRtcSocketBuilder`].
     ///
     /// Update the set of peers used by [`WebRtcSocket::connected_peers`] and
     /// [`WebRtcSocket::disconnected_peers`].
     ///
-    /// Returns the peers that connected or disconnected since the last time
-    /// this method was called.
+    /// Returns the peers whose state changed since the last time this method
+    /// was called.
     ///
     /// See also: [`PeerState`]
     ///
@@ -463,22 +1165,12 @@ impl<C: ChannelPlurality> WebRtcSocket<C> {
     /// Similar to [`WebRtcSocket::update_peers`]. Will instead return a Result::Err if the
     /// socket is closed.
     pub fn try_update_peers(&mut self) -> Result<Vec<(PeerId, PeerState)>, ChannelError> {
+        // Keeps statistics from piling up for sockets that never ask for them
+        self.receive_peer_stats();
         let mut changes = Vec::new();
         while let Ok(res) = self.peer_state_rx.try_next() {
-            debug!("Peer state update: {res:?}");
             match res {
-                Some((id, PeerState::Connected)) => {
-                    let old = self.peers.insert(id, PeerState::Connected);
-                    if old != Some(PeerState::Connected) {
-                        changes.push((id, PeerState::Connected));
-                    }
-                }
-                Some((id, PeerState::Disconnected)) => {
-                    let old = self.peers.insert(id, PeerState::Disconnected);
-                    if old == Some(PeerState::Connected) {
-                        changes.push((id, PeerState::Disconnected));
-                    }
-                }
+                Some((id, state)) => changes.extend(self.apply_peer_state(id, state)),
                 None => return Err(ChannelError::Closed),
             }
         }
@@ -486,11 +1178,60 @@ impl<C: ChannelPlurality> WebRtcSocket<C> {
         Ok(changes)
     }
 
-    pub fn update_signals(&mut self) -> Vec<SignalEvent> {
+    /// Waits for the next change in the state of a peer, without polling.
+    ///
+    /// This is the async equivalent of [`WebRtcSocket::update_peers`], and keeps the set of peers
+    /// up to date in the same way. Returns `None` once the socket is closed.
+    ///
+    /// ```no_run
+    /// use matchbox_socket::*;
+    ///
+    /// # async fn run() {
+    /// let (mut socket, message_loop) = WebRtcSocket::new_reliable("wss://example.invalid/");
+    /// while let Some((peer, state)) = socket.next_peer_event().await {
+    ///     println!("{peer}: {state:?}");
+    /// }
+    /// # }
+    /// ```
+    pub async fn next_peer_event(&mut self) -> Option<(PeerId, PeerState)> {
+        while let Some((id, state)) = self.peer_state_rx.next().await {
+            if let Some(change) = self.apply_peer_state(id, state) {
+                return Some(change);
+            }
+        }
+        None
+    }
+
+    /// Records a peer state update, returning it if it changes the state of the peer
+    fn apply_peer_state(&mut self, id: PeerId, state: PeerState) -> Option<(PeerId, PeerState)> {
+        debug!("Peer state update: {:?}", (id, state));
+        match state {
+            PeerState::Connecting | PeerState::Connected => {
+                let old = self.peers.insert(id, state);
+                (old != Some(state)).then_some((id, state))
+            }
+            PeerState::Disconnected => {
+                self.peer_stats.remove(&id);
+                let old = self.peers.insert(id, PeerState::Disconnected);
+                matches!(old, Some(PeerState::Connecting | PeerState::Connected))
+                    .then_some((id, PeerState::Disconnected))
+            }
+            PeerState::Failed { .. } => {
+                // A peer that left while we were connecting stays disconnected
+                if self.peers.get(&id) != Some(&PeerState::Connecting) {
+                    return None;
+                }
+                self.peers.insert(id, state);
+                Some((id, state))
+            }
+        }
+    }
+
+    pub fn update_signals(&mut self) -> Vec<SignalingEvent> {
         self.try_update_signals().unwrap()
     }
 
-    pub fn try_update_signals(&mut self) -> Result<Vec<SignalEvent>, ChannelError> {
+    pub fn try_update_signals(&mut self) -> Result<Vec<SignalingEvent>, ChannelError> {
         let mut changes = Vec::new();
         while let Ok(res) = self.signal_rx.try_next() {
             match res {
@@ -535,6 +1276,68 @@ impl<C: ChannelPlurality> WebRtcSocket<C> {
         })
     }
 
+    /// Returns the most recent connection statistics for the given peer, or `None` if there
+    /// are none yet.
+    ///
+    /// Statistics are collected at the interval set with
+    /// [`WebRtcSocketBuilder::stats_interval`], while the peer is connected.
+    ///
+    /// Note: You have to call [`WebRtcSocket::update_peers`] for the statistics of
+    /// disconnected peers to be removed.
+    pub fn peer_stats(&mut self, peer: PeerId) -> Option<&PeerStats> {
+        self.receive_peer_stats();
+        self.peer_stats.get(&peer)
+    }
+
+    /// Keeps the latest statistics received for each peer that is still around
+    fn receive_peer_stats(&mut self) {
+        while let Ok(Some((id, stats))) = self.peer_stats_rx.try_next() {
+            if self.peers.get(&id) != Some(&PeerState::Disconnected) {
+                self.peer_stats.insert(id, stats);
+            }
+        }
+    }
+
+    /// Leaves the room: tells the signaling server, so other peers are notified right away, and
+    /// closes the connections to all peers.
+    ///
+    /// The [`MessageLoopFuture`] then finishes with `Ok`, and no more packets are sent or
+    /// received.
+    pub fn close(&mut self) {
+        self.send_command(SocketCommand::Close);
+    }
+
+    /// Closes the connection and data channels to the given peer, who is then reported as
+    /// [`PeerState::Disconnected`]. If the handshake with the peer is still in progress, the
+    /// connection is closed as soon as it completes.
+    ///
+    /// The peer may connect again, e.g. after reconnecting to the signaling server, unless it is
+    /// blocked with [`WebRtcSocket::block_peer`].
+    pub fn disconnect_peer(&mut self, peer: PeerId) {
+        self.send_command(SocketCommand::DisconnectPeer(peer));
+    }
+
+    /// Disconnects from the given peer, and ignores its offers and new peer announcements from
+    /// then on, so it can't connect again until it is unblocked.
+    pub fn block_peer(&mut self, peer: PeerId) {
+        self.send_command(SocketCommand::BlockPeer(peer));
+    }
+
+    /// Allows a peer blocked with [`WebRtcSocket::block_peer`] to connect again.
+    ///
+    /// Peers already in the room are not connected to automatically, the peer has to make a new
+    /// offer, e.g. after reconnecting to the signaling server.
+    pub fn unblock_peer(&mut self, peer: PeerId) {
+        self.send_command(SocketCommand::UnblockPeer(peer));
+    }
+
+    fn send_command(&self, command: SocketCommand) {
+        if self.commands_tx.unbounded_send(command).is_err() {
+            // The message loop is already gone, so there is nothing left to do
+            debug!("ignoring command, the message loop has finished");
+        }
+    }
+
     /// Returns the id of this peer, this may be `None` if an id has not yet
     /// been assigned by the server.
     pub fn id(&mut self) -> Option<PeerId> {
@@ -624,17 +1427,39 @@ impl WebRtcSocket<SingleChannel> {
     }
 
     /// Try to send a packet to the given peer. An error is propagated if the socket future
-    /// is dropped. `Ok` is not a guarantee of delivery.
+    /// is dropped, or if the peer's send queue is full. `Ok` is not a guarantee of delivery.
     pub fn try_send(&mut self, packet: Packet, peer: PeerId) -> Result<(), SendError> {
         self.channel(0).try_send(packet, peer)
     }
 
     /// Send a packet to the given peer. There is no guarantee of delivery.
     ///
+    /// If the peer's send queue is full, the packet is dropped.
+    ///
     /// # Panics
     /// Panics if socket future is dropped.
     pub fn send(&mut self, packet: Packet, peer: PeerId) {
-        self.try_send(packet, peer).expect("Send failed");
+        self.channel(0).send(packet, peer);
+    }
+
+    /// Sends a packet to every peer whose data channels are currently open, returning the peers
+    /// it could not be queued for.
+    ///
+    /// See also: [`WebRtcChannel::broadcast`]
+    pub fn broadcast(&mut self, packet: Packet) -> Vec<(PeerId, SendError)> {
+        self.channel(0).broadcast(packet)
+    }
+
+    /// Sends a packet to each of the given peers, returning the peers it could not be queued
+    /// for.
+    ///
+    /// See also: [`WebRtcChannel::send_to_many`]
+    pub fn send_to_many(
+        &mut self,
+        packet: Packet,
+        peers: impl IntoIterator<Item = PeerId>,
+    ) -> Vec<(PeerId, SendError)> {
+        self.channel(0).send_to_many(packet, peers)
     }
 }
 
@@ -670,29 +1495,55 @@ async fn wait_for_ready(channel_ready_rx: Vec<futures_channel::mpsc::Receiver<()
 /// All the channels needed for the messaging loop.
 pub struct MessageLoopChannels {
     pub requests_sender: futures_channel::mpsc::UnboundedSender<PeerRequest>,
-    pub events_receiver: futures_channel::mpsc::UnboundedReceiver<SignalEvent>,
-    pub peer_messages_out_rx: Vec<futures_channel::mpsc::UnboundedReceiver<(PeerId, Packet)>>,
-    pub signal_tx: futures_channel::mpsc::UnboundedSender<SignalEvent>,
+    pub events_receiver: futures_channel::mpsc::UnboundedReceiver<SignalingEvent>,
+    pub peer_messages_out_rx: Vec<futures_channel::mpsc::UnboundedReceiver<(PeerId, QueuedPacket)>>,
+    pub signal_tx: futures_channel::mpsc::UnboundedSender<SignalingEvent>,
     pub peer_state_tx: futures_channel::mpsc::UnboundedSender<(PeerId, PeerState)>,
+    pub peer_stats_tx: futures_channel::mpsc::UnboundedSender<(PeerId, PeerStats)>,
     pub messages_from_peers_tx: Vec<futures_channel::mpsc::UnboundedSender<(PeerId, Packet)>>,
+    pub connected_peers: ConnectedPeers,
+    pub commands_rx: futures_channel::mpsc::UnboundedReceiver<SocketCommand>,
 }
 
+/// Requests from the [`WebRtcSocket`] to its message loop
+#[derive(Debug)]
+pub(crate) enum SocketCommand {
+    Close,
+    DisconnectPeer(PeerId),
+    BlockPeer(PeerId),
+    UnblockPeer(PeerId),
+}
+
+/// How long a finished message loop waits for queued signaling requests to be delivered
+const SIGNALING_FLUSH_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[allow(clippy::too_many_arguments)]
 async fn run_socket(
     id_tx: futures_channel::oneshot::Sender<PeerId>,
     config: SocketConfig,
-    peer_messages_out_rx: Vec<futures_channel::mpsc::UnboundedReceiver<(PeerId, Packet)>>,
-    signal_tx: futures_channel::mpsc::UnboundedSender<SignalEvent>,
+    peer_messages_out_rx: Vec<futures_channel::mpsc::UnboundedReceiver<(PeerId, QueuedPacket)>>,
+    signal_tx: futures_channel::mpsc::UnboundedSender<SignalingEvent>,
     peer_state_tx: futures_channel::mpsc::UnboundedSender<(PeerId, PeerState)>,
+    peer_stats_tx: futures_channel::mpsc::UnboundedSender<(PeerId, PeerStats)>,
     messages_from_peers_tx: Vec<futures_channel::mpsc::UnboundedSender<(PeerId, Packet)>>,
+    connected_peers: ConnectedPeers,
+    commands_rx: futures_channel::mpsc::UnboundedReceiver<SocketCommand>,
 ) -> Result<(), SignalingError> {
     debug!("Starting WebRtcSocket");
 
     let (requests_sender, requests_receiver) = futures_channel::mpsc::unbounded::<PeerRequest>();
-    let (events_sender, events_receiver) = futures_channel::mpsc::unbounded::<SignalEvent>();
+    let (events_sender, events_receiver) = futures_channel::mpsc::unbounded::<SignalingEvent>();
 
-    let signaling_loop_fut = signaling_loop::<UseSignaller>(
+    let room_url = match &config.signaling_token {
+        Some(token) => with_query_param(&config.room_url, AUTH_TOKEN_QUERY_PARAM, token),
+        None => config.room_url.clone(),
+    };
+    let signaling_loop_fut = signaling_loop(
+        config.signaller_builder(),
         config.attempts,
-        config.room_url,
+        config.reconnect_policy.clone(),
+        room_url,
+        config.strict_signaling,
         requests_receiver,
         events_sender,
     );
@@ -703,15 +1554,20 @@ async fn run_socket(
         peer_messages_out_rx,
         signal_tx,
         peer_state_tx,
+        peer_stats_tx,
         messages_from_peers_tx,
+        connected_peers,
+        commands_rx,
     };
-    let message_loop_fut = message_loop::<UseMessenger>(
-        id_tx,
-        &config.ice_server,
-        &config.channels,
-        channels,
-        config.keep_alive_interval,
-    );
+    #[cfg(all(feature = "loopback", not(target_arch = "wasm32")))]
+    let message_loop_fut = if let Some(network) = config.loopback.clone() {
+        let messenger = LoopbackMessenger::new(network);
+        run_message_loop(messenger, id_tx, &config, channels).left_future()
+    } else {
+        run_message_loop(UseMessenger::default(), id_tx, &config, channels).right_future()
+    };
+    #[cfg(not(all(feature = "loopback", not(target_arch = "wasm32"))))]
+    let message_loop_fut = run_message_loop(UseMessenger::default(), id_tx, &config, channels);
 
     let mut message_loop_done = Box::pin(message_loop_fut.fuse());
     let mut signaling_loop_done = Box::pin(signaling_loop_fut.fuse());
@@ -721,10 +1577,22 @@ async fn run_socket(
                 match msgloop {
                     Ok(()) => {
                         debug!("Message loop completed");
+                        // Let the signaling loop deliver the requests that are still queued,
+                        // e.g. leaving the room after the socket was closed. It finishes once the
+                        // message loop's request sender is gone.
+                        if !signaling_loop_done.is_terminated() {
+                            select! {
+                                sigloop = signaling_loop_done => if let Err(e) = sigloop {
+                                    warn!("failed to flush signaling requests: {e:?}");
+                                },
+                                _ = Delay::new(SIGNALING_FLUSH_TIMEOUT).fuse() => {
+                                    warn!("gave up flushing signaling requests");
+                                }
+                            }
+                        }
                         break Ok(())
                     },
                     Err(e) => {
-                        // TODO: Reconnect X attempts if configured to reconnect.
                         error!("The message loop finished with an error: {e:?}");
                         break Err(e);
                     },
@@ -735,7 +1603,8 @@ async fn run_socket(
                 match sigloop {
                     Ok(()) => debug!("Signaling loop completed"),
                     Err(e) => {
-                        // TODO: Reconnect X attempts if configured to reconnect.
+                        // Reconnection attempts are handled by the signaling loop itself, so
+                        // getting here means they were exhausted or disabled.
                         error!("The signaling loop finished with an error: {e:?}");
                         break Err(e);
                     },
@@ -747,11 +1616,46 @@ async fn run_socket(
     }
 }
 
+async fn run_message_loop<M: Messenger>(
+    messenger: M,
+    id_tx: futures_channel::oneshot::Sender<PeerId>,
+    config: &SocketConfig,
+    channels: MessageLoopChannels,
+) -> Result<(), SignalingError> {
+    message_loop(
+        &messenger,
+        id_tx,
+        &config.ice,
+        &config.channels,
+        channels,
+        config.keep_alive_interval,
+        config.handshake_timeout,
+        config.stats_interval,
+        config.conditioner.as_ref(),
+        &config.peer_policy,
+    )
+    .await
+}
+
 #[cfg(test)]
 mod test {
-    use crate::{ChannelConfig, Error, WebRtcSocketBuilder};
+    use crate::{
+        webrtc_socket::SignalEvent, ChannelConfig, ConnectionFailure, Error, Packet, PeerId,
+        PeerState, SendError, SignalingError, SignalingReconnectPolicy, Signaller,
+        SignallerBuilder, WebRtcSocketBuilder,
+    };
+    use futures::{FutureExt, SinkExt, StreamExt};
+    use futures_timer::Delay;
+    use matchbox_protocol::{PeerEvent, ResumeToken};
+    use std::{
+        collections::VecDeque,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
 
-    #[futures_test::test]
+    // Without async-std, signaling has to run on tokio
+    #[cfg_attr(feature = "async-std", futures_test::test)]
+    #[cfg_attr(not(feature = "async-std"), tokio::test)]
     async fn unreachable_server() {
         // .invalid is a reserved tld for testing and documentation
         let (_socket, fut) = WebRtcSocketBuilder::new("wss://example.invalid")
@@ -766,7 +1670,9 @@ mod test {
         ));
     }
 
-    #[futures_test::test]
+    // Without async-std, signaling has to run on tokio
+    #[cfg_attr(feature = "async-std", futures_test::test)]
+    #[cfg_attr(not(feature = "async-std"), tokio::test)]
     async fn test_signaling_attempts() {
         let (_socket, loop_fut) = WebRtcSocketBuilder::new("wss://example.invalid/")
             .reconnect_attempts(Some(3))
@@ -780,4 +1686,463 @@ mod test {
             Error::ConnectionFailed { .. },
         ));
     }
+
+    #[test]
+    fn reconnect_policy_backs_off_exponentially() {
+        let policy = SignalingReconnectPolicy {
+            max_attempts: None,
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(5),
+            multiplier: 2.0,
+        };
+
+        assert_eq!(policy.delay(0), Duration::from_millis(500));
+        assert_eq!(policy.delay(1), Duration::from_secs(1));
+        assert_eq!(policy.delay(3), Duration::from_secs(4));
+        assert_eq!(policy.delay(4), Duration::from_secs(5));
+        assert_eq!(policy.delay(u16::MAX), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn failed_handshake_is_reported_only_while_connecting() {
+        let (mut socket, _loop_fut) = WebRtcSocketBuilder::new("wss://example.invalid/")
+            .add_channel(ChannelConfig::reliable())
+            .build();
+        let (peer_state_tx, peer_state_rx) = futures_channel::mpsc::unbounded();
+        socket.peer_state_rx = peer_state_rx;
+
+        let failed = PeerState::Failed {
+            reason: ConnectionFailure::HandshakeTimeout(Duration::from_secs(1)),
+        };
+        let timed_out = PeerId(uuid::Uuid::from_u128(1));
+        let left = PeerId(uuid::Uuid::from_u128(2));
+        for update in [
+            (timed_out, PeerState::Connecting),
+            (left, PeerState::Connecting),
+            (left, PeerState::Disconnected),
+            (timed_out, failed),
+            (left, failed),
+        ] {
+            peer_state_tx.unbounded_send(update).unwrap();
+        }
+
+        assert_eq!(
+            socket.update_peers(),
+            vec![
+                (timed_out, PeerState::Connecting),
+                (left, PeerState::Connecting),
+                (left, PeerState::Disconnected),
+                (timed_out, failed),
+            ]
+        );
+        assert_eq!(socket.disconnected_peers().collect::<Vec<_>>(), vec![&left]);
+    }
+
+    #[test]
+    fn bounded_send_queue_would_block() {
+        let (mut socket, loop_fut) = WebRtcSocketBuilder::new("wss://example.invalid/")
+            .add_channel(ChannelConfig::unreliable().send_queue_capacity(Some(2)))
+            .build();
+        let peer = PeerId(uuid::Uuid::from_u128(1));
+        let other_peer = PeerId(uuid::Uuid::from_u128(2));
+
+        // The message loop isn't running, so nothing leaves the queue
+        socket.try_send(Box::new([0]), peer).unwrap();
+        socket.try_send(Box::new([1]), peer).unwrap();
+        assert!(matches!(
+            socket.try_send(Box::new([2]), peer),
+            Err(SendError::WouldBlock)
+        ));
+        socket.try_send(Box::new([3]), other_peer).unwrap();
+        assert_eq!(socket.channel(0).queued_packets(peer), 2);
+        assert_eq!(socket.channel(0).queued_packets(other_peer), 1);
+
+        // Dropping the message loop discards the queued packets
+        drop(loop_fut);
+        assert_eq!(socket.channel(0).queued_packets(peer), 0);
+        assert!(matches!(
+            socket.try_send(Box::new([4]), peer),
+            Err(SendError::Closed)
+        ));
+    }
+
+    #[futures_test::test]
+    async fn next_peer_event_skips_unchanged_states() {
+        let (mut socket, _loop_fut) = WebRtcSocketBuilder::new("wss://example.invalid/")
+            .add_channel(ChannelConfig::reliable())
+            .build();
+        let (peer_state_tx, peer_state_rx) = futures_channel::mpsc::unbounded();
+        socket.peer_state_rx = peer_state_rx;
+
+        let peer = PeerId(uuid::Uuid::from_u128(1));
+        for state in [
+            PeerState::Connecting,
+            PeerState::Connecting,
+            PeerState::Connected,
+        ] {
+            peer_state_tx.unbounded_send((peer, state)).unwrap();
+        }
+        drop(peer_state_tx);
+
+        assert_eq!(
+            socket.next_peer_event().await,
+            Some((peer, PeerState::Connecting))
+        );
+        assert_eq!(
+            socket.next_peer_event().await,
+            Some((peer, PeerState::Connected))
+        );
+        assert_eq!(socket.next_peer_event().await, None);
+        assert_eq!(socket.connected_peers().collect::<Vec<_>>(), vec![peer]);
+    }
+
+    #[futures_test::test]
+    async fn channel_is_a_stream_and_sink() {
+        let (mut socket, _loop_fut) = WebRtcSocketBuilder::new("wss://example.invalid/")
+            .add_channel(ChannelConfig::reliable())
+            .build();
+        let mut channel = socket.take_channel(0).unwrap();
+        let (messages_tx, messages_rx) = futures_channel::mpsc::unbounded();
+        channel.rx = messages_rx;
+        let (outgoing_tx, mut outgoing_rx) = futures_channel::mpsc::unbounded();
+        channel.tx = outgoing_tx;
+
+        let peer = PeerId(uuid::Uuid::from_u128(1));
+        let packet: Packet = Box::new([1, 2]);
+        messages_tx.unbounded_send((peer, packet.clone())).unwrap();
+        drop(messages_tx);
+        assert_eq!(channel.next().await, Some((peer, packet)));
+        assert_eq!(channel.next().await, None);
+
+        SinkExt::send(&mut channel, (peer, Box::new([3u8]) as Packet))
+            .await
+            .unwrap();
+        let (to, sent) = outgoing_rx.next().await.unwrap();
+        assert_eq!((to, &sent.packet[..]), (peer, &[3][..]));
+
+        drop(outgoing_rx);
+        assert!(matches!(
+            SinkExt::send(&mut channel, (peer, Box::new([4u8]) as Packet)).await,
+            Err(SendError::Closed)
+        ));
+    }
+
+    #[futures_test::test]
+    async fn sink_waits_for_room_in_send_queue() {
+        let (mut socket, _loop_fut) = WebRtcSocketBuilder::new("wss://example.invalid/")
+            .add_channel(ChannelConfig::unreliable().send_queue_capacity(Some(1)))
+            .build();
+        let mut channel = socket.take_channel(0).unwrap();
+        let (outgoing_tx, mut outgoing_rx) = futures_channel::mpsc::unbounded();
+        channel.tx = outgoing_tx;
+
+        let peer = PeerId(uuid::Uuid::from_u128(1));
+        SinkExt::send(&mut channel, (peer, Box::new([0u8]) as Packet))
+            .await
+            .unwrap();
+        let (_, queued) = outgoing_rx.next().await.unwrap();
+
+        let mut send = SinkExt::send(&mut channel, (peer, Box::new([1u8]) as Packet));
+        assert!(futures::poll!(&mut send).is_pending());
+        drop(queued);
+        send.await.unwrap();
+        let (_, sent) = outgoing_rx.next().await.unwrap();
+        assert_eq!(&sent.packet[..], &[1]);
+    }
+
+    #[futures_test::test]
+    async fn broadcast_shares_packet_and_reports_full_queues() {
+        let (mut socket, _loop_fut) = WebRtcSocketBuilder::new("wss://example.invalid/")
+            .add_channel(ChannelConfig::unreliable().send_queue_capacity(Some(1)))
+            .build();
+        let mut channel = socket.take_channel(0).unwrap();
+        let (outgoing_tx, mut outgoing_rx) = futures_channel::mpsc::unbounded();
+        channel.tx = outgoing_tx;
+
+        let busy = PeerId(uuid::Uuid::from_u128(1));
+        let idle = PeerId(uuid::Uuid::from_u128(2));
+        let other = PeerId(uuid::Uuid::from_u128(3));
+        channel.connected_peers.insert(busy);
+        channel.connected_peers.insert(idle);
+        channel.try_send(Box::new([0]), busy).unwrap();
+        let (_, queued) = outgoing_rx.next().await.unwrap();
+
+        let failed = channel.broadcast(Box::new([1]));
+        assert!(matches!(failed[..], [(peer, SendError::WouldBlock)] if peer == busy));
+        drop(queued);
+
+        let failed = channel.send_to_many(Box::new([2]), [busy, other]);
+        assert!(failed.is_empty());
+        let mut sent = vec![];
+        while let Ok(Some(message)) = outgoing_rx.try_next() {
+            sent.push(message);
+        }
+        assert_eq!(sent.len(), 3);
+        assert_eq!((sent[0].0, &sent[0].1.packet[..]), (idle, &[1][..]));
+        // Both peers get the same buffer
+        assert_eq!(sent[1].1.packet.as_ptr(), sent[2].1.packet.as_ptr());
+    }
+
+    #[derive(Debug, Default)]
+    struct UnreachableSignallerBuilder {
+        room_urls: Mutex<Vec<String>>,
+    }
+
+    #[async_trait::async_trait]
+    impl SignallerBuilder for Arc<UnreachableSignallerBuilder> {
+        async fn new_signaller(
+            &self,
+            _attempts: Option<u16>,
+            room_url: String,
+        ) -> Result<Box<dyn Signaller>, SignalingError> {
+            self.room_urls.lock().unwrap().push(room_url);
+            Err(SignalingError::NegotiationFailed(Box::new(
+                SignalingError::Custom("no route to lobby".into()),
+            )))
+        }
+    }
+
+    #[futures_test::test]
+    async fn custom_signaller_builder_is_used() {
+        let builder = Arc::new(UnreachableSignallerBuilder::default());
+        let (_socket, loop_fut) = WebRtcSocketBuilder::new("lobby://room")
+            .signaller_builder(builder.clone())
+            .add_channel(ChannelConfig::reliable())
+            .build();
+
+        assert!(matches!(loop_fut.await, Err(Error::ConnectionFailed(_))));
+        assert_eq!(
+            *builder.room_urls.lock().unwrap(),
+            vec!["lobby://room?resumable=true"]
+        );
+    }
+
+    #[futures_test::test]
+    async fn signaling_token_is_added_to_room_url() {
+        let builder = Arc::new(UnreachableSignallerBuilder::default());
+        let (_socket, loop_fut) = WebRtcSocketBuilder::new("lobby://room?next=2")
+            .signaller_builder(builder.clone())
+            .signaling_token("ticket+1/2=")
+            .add_channel(ChannelConfig::reliable())
+            .build();
+
+        assert!(loop_fut.await.is_err());
+        assert_eq!(
+            *builder.room_urls.lock().unwrap(),
+            vec!["lobby://room?next=2&token=ticket%2B1%2F2%3D&resumable=true"]
+        );
+    }
+
+    /// Receives the given messages, then waits forever
+    #[derive(Debug, Clone)]
+    struct ScriptedSignallerBuilder(Vec<String>);
+
+    struct ScriptedSignaller(VecDeque<String>);
+
+    #[async_trait::async_trait]
+    impl SignallerBuilder for ScriptedSignallerBuilder {
+        async fn new_signaller(
+            &self,
+            _attempts: Option<u16>,
+            _room_url: String,
+        ) -> Result<Box<dyn Signaller>, SignalingError> {
+            Ok(Box::new(ScriptedSignaller(self.0.clone().into())))
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl Signaller for ScriptedSignaller {
+        async fn send(&mut self, _request: String) -> Result<(), SignalingError> {
+            Ok(())
+        }
+
+        async fn next_message(&mut self) -> Result<String, SignalingError> {
+            match self.0.pop_front() {
+                Some(message) => Ok(message),
+                None => std::future::pending().await,
+            }
+        }
+    }
+
+    fn malformed_then_id_assigned(id: PeerId) -> ScriptedSignallerBuilder {
+        let id_assigned = SignalEvent::Peer(PeerEvent::IdAssigned(id));
+        ScriptedSignallerBuilder(vec![
+            r#"{"Peer":{"SomethingNew":1}}"#.to_string(),
+            "not json".to_string(),
+            serde_json::to_string(&id_assigned).unwrap(),
+        ])
+    }
+
+    #[futures_test::test]
+    async fn malformed_signaling_messages_are_ignored() {
+        let id = PeerId(uuid::Uuid::from_u128(1));
+        let (mut socket, loop_fut) = WebRtcSocketBuilder::new("wss://example.invalid/")
+            .signaller_builder(malformed_then_id_assigned(id))
+            .add_channel(ChannelConfig::reliable())
+            .build();
+
+        let assigned_id = async {
+            loop {
+                if let Some(id) = socket.id() {
+                    break id;
+                }
+                Delay::new(Duration::from_millis(1)).await;
+            }
+        };
+        futures::select! {
+            result = loop_fut.fuse() => panic!("message loop finished early: {result:?}"),
+            assigned_id = Box::pin(assigned_id.fuse()) => assert_eq!(assigned_id, id),
+        }
+    }
+
+    #[cfg(feature = "loopback")]
+    #[futures_test::test]
+    async fn only_offers_start_handshakes() {
+        use crate::{webrtc_socket::PeerSignal, LoopbackNetwork};
+
+        let id = PeerId(uuid::Uuid::from_u128(1));
+        let straggler = PeerId(uuid::Uuid::from_u128(2));
+        let offerer = PeerId(uuid::Uuid::from_u128(3));
+        let events = [
+            SignalEvent::Peer(PeerEvent::IdAssigned(id)),
+            SignalEvent::Peer(PeerEvent::Signal {
+                sender: straggler,
+                data: PeerSignal::IceCandidate("candidate".to_string()),
+            }),
+            SignalEvent::Peer(PeerEvent::Signal {
+                sender: offerer,
+                data: PeerSignal::Offer("offer".to_string()),
+            }),
+        ];
+        let script = events
+            .iter()
+            .map(|event| serde_json::to_string(event).unwrap());
+        let (mut socket, loop_fut) = WebRtcSocketBuilder::new("room")
+            .loopback(&LoopbackNetwork::default())
+            .signaller_builder(ScriptedSignallerBuilder(script.collect()))
+            .add_channel(ChannelConfig::reliable())
+            .build();
+
+        futures::select! {
+            result = loop_fut.fuse() => panic!("message loop finished early: {result:?}"),
+            event = Box::pin(socket.next_peer_event().fuse()) => {
+                assert_eq!(event, Some((offerer, PeerState::Connecting)));
+            }
+        }
+    }
+
+    #[futures_test::test]
+    async fn malformed_signaling_messages_fail_in_strict_mode() {
+        let (_socket, loop_fut) = WebRtcSocketBuilder::new("wss://example.invalid/")
+            .signaller_builder(malformed_then_id_assigned(PeerId(uuid::Uuid::from_u128(1))))
+            .strict_signaling(true)
+            .add_channel(ChannelConfig::reliable())
+            .build();
+
+        assert!(matches!(
+            loop_fut.await,
+            Err(Error::Disconnected(SignalingError::MalformedMessage { message, .. }))
+                if message == r#"{"Peer":{"SomethingNew":1}}"#
+        ));
+    }
+
+    /// Its connection is closed right away
+    #[derive(Debug, Clone)]
+    struct ClosedSignallerBuilder;
+
+    /// Receives the given messages, then its connection is closed
+    struct ClosedSignaller(VecDeque<String>);
+
+    #[async_trait::async_trait]
+    impl SignallerBuilder for ClosedSignallerBuilder {
+        async fn new_signaller(
+            &self,
+            _attempts: Option<u16>,
+            _room_url: String,
+        ) -> Result<Box<dyn Signaller>, SignalingError> {
+            Ok(Box::new(ClosedSignaller(VecDeque::new())))
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl Signaller for ClosedSignaller {
+        async fn send(&mut self, _request: String) -> Result<(), SignalingError> {
+            Ok(())
+        }
+
+        async fn next_message(&mut self) -> Result<String, SignalingError> {
+            self.0.pop_front().ok_or(SignalingError::StreamExhausted)
+        }
+    }
+
+    #[futures_test::test]
+    async fn closed_signaling_connection_disconnects() {
+        let (_socket, loop_fut) = WebRtcSocketBuilder::new("wss://example.invalid/")
+            .signaller_builder(ClosedSignallerBuilder)
+            .signaling_reconnect_policy(None)
+            .add_channel(ChannelConfig::reliable())
+            .build();
+
+        assert!(matches!(
+            loop_fut.await,
+            Err(Error::Disconnected(SignalingError::StreamExhausted))
+        ));
+    }
+
+    /// Hands out a resume token on the first connection, then can't reconnect
+    #[derive(Debug, Default)]
+    struct ResumableSignallerBuilder {
+        room_urls: Mutex<Vec<String>>,
+    }
+
+    #[async_trait::async_trait]
+    impl SignallerBuilder for Arc<ResumableSignallerBuilder> {
+        async fn new_signaller(
+            &self,
+            _attempts: Option<u16>,
+            room_url: String,
+        ) -> Result<Box<dyn Signaller>, SignalingError> {
+            let mut room_urls = self.room_urls.lock().unwrap();
+            room_urls.push(room_url);
+            if room_urls.len() > 1 {
+                return Err(SignalingError::NegotiationFailed(Box::new(
+                    SignalingError::Custom("no route to lobby".into()),
+                )));
+            }
+            let id = PeerId(uuid::Uuid::from_u128(1));
+            let events = [
+                PeerEvent::IdAssigned(id),
+                PeerEvent::ResumeToken(ResumeToken(uuid::Uuid::from_u128(2))),
+            ];
+            let script = events
+                .into_iter()
+                .map(|event| serde_json::to_string(&SignalEvent::Peer(event)).unwrap())
+                .collect();
+            Ok(Box::new(ClosedSignaller(script)))
+        }
+    }
+
+    #[futures_test::test]
+    async fn resume_token_is_presented_when_reconnecting() {
+        let builder = Arc::new(ResumableSignallerBuilder::default());
+        let (_socket, loop_fut) = WebRtcSocketBuilder::new("lobby://room")
+            .signaller_builder(builder.clone())
+            .signaling_reconnect_policy(Some(SignalingReconnectPolicy {
+                max_attempts: Some(1),
+                initial_delay: Duration::from_millis(1),
+                max_delay: Duration::from_millis(1),
+                multiplier: 1.0,
+            }))
+            .add_channel(ChannelConfig::reliable())
+            .build();
+
+        assert!(loop_fut.await.is_err());
+        assert_eq!(
+            *builder.room_urls.lock().unwrap(),
+            vec![
+                "lobby://room?resumable=true",
+                "lobby://room?resumable=true&resume_token=00000000-0000-0000-0000-000000000002",
+            ]
+        );
+    }
 }
@@ -0,0 +1,68 @@
+use futures::{
+    future::{Either, Fuse, Pending},
+    FutureExt,
+};
+use futures_timer::Delay;
+use std::time::Duration;
+
+/// How an ICE candidate was obtained
+/// See also: <https://developer.mozilla.org/en-US/docs/Web/API/RTCIceCandidate/type>
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IceCandidateType {
+    /// The candidate is one of the peer's own addresses
+    Host,
+    /// The candidate is the peer's public address, as seen by a STUN server
+    ServerReflexive,
+    /// The candidate is the peer's public address, as seen by the other peer
+    PeerReflexive,
+    /// The candidate is a TURN server relaying traffic for the peer
+    Relay,
+}
+
+/// Traffic statistics for one of the [`WebRtcChannel`](crate::WebRtcChannel)s to a peer
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChannelStats {
+    /// Payload bytes sent on the channel
+    pub bytes_sent: u64,
+    /// Payload bytes received on the channel
+    pub bytes_received: u64,
+    /// Packets sent on the channel
+    pub packets_sent: u64,
+    /// Packets received on the channel
+    pub packets_received: u64,
+    /// Bytes queued on the data channel that haven't been handed to the network yet
+    /// See also: <https://developer.mozilla.org/en-US/docs/Web/API/RTCDataChannel/bufferedAmount>
+    pub buffered_amount: u64,
+}
+
+/// A snapshot of the connection statistics for a peer
+///
+/// See also: [`WebRtcSocket::peer_stats`](crate::WebRtcSocket::peer_stats)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PeerStats {
+    /// The most recent round trip time measured on the selected ICE candidate pair
+    pub round_trip_time: Option<Duration>,
+    /// The type of our side of the selected ICE candidate pair
+    pub local_candidate_type: Option<IceCandidateType>,
+    /// The type of the peer's side of the selected ICE candidate pair
+    pub remote_candidate_type: Option<IceCandidateType>,
+    /// Statistics for each channel, in the order the channels were added to the socket
+    pub channels: Vec<ChannelStats>,
+}
+
+impl PeerStats {
+    /// Whether traffic to the peer goes through a TURN server on either side
+    pub fn is_relayed(&self) -> bool {
+        self.local_candidate_type == Some(IceCandidateType::Relay)
+            || self.remote_candidate_type == Some(IceCandidateType::Relay)
+    }
+}
+
+/// Creates a timer for the next stats collection, which never fires if stats are disabled.
+pub(crate) fn stats_timer(interval: Option<Duration>) -> Fuse<Either<Delay, Pending<()>>> {
+    match interval {
+        Some(interval) => Either::Left(Delay::new(interval)),
+        None => Either::Right(futures::future::pending()),
+    }
+    .fuse()
+}
@@ -1,11 +1,14 @@
-use super::{error::JsErrorExt, HandshakeResult, PacketSendError, PeerDataSender};
+use super::{
+    error::JsErrorExt, send_queue::QueuedPacket, HandshakeResult, PacketSendError, PeerDataSender,
+};
 use crate::webrtc_socket::{
     error::SignalingError, messages::PeerSignal, signal_peer::SignalPeer,
-    socket::create_data_channels_ready_fut, ChannelConfig, Messenger, Packet, RtcIceServerConfig,
-    Signaller,
+    socket::create_data_channels_ready_fut, stats::stats_timer, ChannelConfig, ChannelStats,
+    IceCandidateType, IceConfig, Messenger, Packet, PeerStats, RtcIceTransportPolicy, Signaller,
+    SignallerBuilder,
 };
 use async_trait::async_trait;
-use futures::{Future, SinkExt, StreamExt};
+use futures::{future::Fuse, Future, FutureExt, SinkExt, StreamExt};
 use futures_channel::mpsc::{Receiver, UnboundedReceiver, UnboundedSender};
 use futures_timer::Delay;
 use futures_util::select;
@@ -13,7 +16,7 @@ use js_sys::{Function, Reflect};
 use log::{debug, error, info, trace, warn};
 use matchbox_protocol::PeerId;
 use serde::Serialize;
-use std::{pin::Pin, time::Duration};
+use std::{collections::HashMap, pin::Pin, time::Duration};
 use wasm_bindgen::{convert::FromWasmAbi, prelude::*, JsCast, JsValue};
 use wasm_bindgen_futures::JsFuture;
 use web_sys::{
@@ -27,11 +30,18 @@ pub(crate) struct WasmSignaller {
     websocket_stream: futures::stream::Fuse<WsStream>,
 }
 
+#[derive(Debug, Default)]
+pub(crate) struct WasmSignallerBuilder;
+
 #[async_trait(?Send)]
-impl Signaller for WasmSignaller {
-    async fn new(mut attempts: Option<u16>, room_url: &str) -> Result<Self, SignalingError> {
+impl SignallerBuilder for WasmSignallerBuilder {
+    async fn new_signaller(
+        &self,
+        mut attempts: Option<u16>,
+        room_url: String,
+    ) -> Result<Box<dyn Signaller>, SignalingError> {
         let websocket_stream = 'signaling: loop {
-            match WsMeta::connect(room_url, None)
+            match WsMeta::connect(&room_url, None)
                 .await
                 .map_err(SignalingError::from)
             {
@@ -54,9 +64,12 @@ impl Signaller for WasmSignaller {
                 }
             };
         };
-        Ok(Self { websocket_stream })
+        Ok(Box::new(WasmSignaller { websocket_stream }))
     }
+}
 
+#[async_trait(?Send)]
+impl Signaller for WasmSignaller {
     async fn send(&mut self, request: String) -> Result<(), SignalingError> {
         self.websocket_stream
             .send(WsMessage::Text(request))
@@ -74,30 +87,36 @@ impl Signaller for WasmSignaller {
 }
 
 impl PeerDataSender for RtcDataChannel {
-    fn send(&mut self, packet: Packet) -> Result<(), PacketSendError> {
-        self.send_with_u8_array(&packet)
+    fn send(&mut self, packet: QueuedPacket) -> Result<(), PacketSendError> {
+        self.send_with_u8_array(&packet.packet)
             .efix()
             .map_err(|source| PacketSendError { source })
     }
+
+    fn close(&mut self) {
+        RtcDataChannel::close(self);
+    }
 }
 
+#[derive(Default)]
 pub(crate) struct WasmMessenger;
 
 #[async_trait(?Send)]
 impl Messenger for WasmMessenger {
     type DataChannel = RtcDataChannel;
-    type HandshakeMeta = Receiver<()>;
+    type HandshakeMeta = (Receiver<()>, RtcPeerConnection, Vec<RtcDataChannel>);
 
     async fn offer_handshake(
+        &self,
         signal_peer: SignalPeer,
         mut peer_signal_rx: UnboundedReceiver<PeerSignal>,
         messages_from_peers_tx: Vec<UnboundedSender<(PeerId, Packet)>>,
-        ice_server_config: &RtcIceServerConfig,
+        ice_config: &IceConfig,
         channel_configs: &[ChannelConfig],
     ) -> HandshakeResult<Self::DataChannel, Self::HandshakeMeta> {
         debug!("making offer");
 
-        let conn = create_rtc_peer_connection(ice_server_config);
+        let conn = create_rtc_peer_connection(ice_config);
 
         let (data_channel_ready_txs, data_channels_ready_fut) =
             create_data_channels_ready_fut(channel_configs);
@@ -170,7 +189,7 @@ impl Messenger for WasmMessenger {
 
         complete_handshake(
             signal_peer.clone(),
-            conn,
+            conn.clone(),
             received_candidates,
             data_channels_ready_fut,
             peer_signal_rx,
@@ -179,21 +198,22 @@ impl Messenger for WasmMessenger {
 
         HandshakeResult {
             peer_id: signal_peer.id,
-            data_channels,
-            metadata: peer_disconnected_rx,
+            data_channels: data_channels.clone(),
+            metadata: (peer_disconnected_rx, conn, data_channels),
         }
     }
 
     async fn accept_handshake(
+        &self,
         signal_peer: SignalPeer,
         mut peer_signal_rx: UnboundedReceiver<PeerSignal>,
         messages_from_peers_tx: Vec<UnboundedSender<(PeerId, Packet)>>,
-        ice_server_config: &RtcIceServerConfig,
+        ice_config: &IceConfig,
         channel_configs: &[ChannelConfig],
     ) -> HandshakeResult<Self::DataChannel, Self::HandshakeMeta> {
         debug!("handshake_accept");
 
-        let conn = create_rtc_peer_connection(ice_server_config);
+        let conn = create_rtc_peer_connection(ice_config);
 
         let (data_channel_ready_txs, data_channels_ready_fut) =
             create_data_channels_ready_fut(channel_configs);
@@ -275,7 +295,7 @@ impl Messenger for WasmMessenger {
 
         complete_handshake(
             signal_peer.clone(),
-            conn,
+            conn.clone(),
             received_candidates,
             data_channels_ready_fut,
             peer_signal_rx,
@@ -284,18 +304,143 @@ impl Messenger for WasmMessenger {
 
         HandshakeResult {
             peer_id: signal_peer.id,
-            data_channels,
-            metadata: peer_disconnected_rx,
+            data_channels: data_channels.clone(),
+            metadata: (peer_disconnected_rx, conn, data_channels),
         }
     }
 
-    async fn peer_loop(peer_uuid: PeerId, handshake_meta: Self::HandshakeMeta) -> PeerId {
-        let mut peer_loop_finished_rx = handshake_meta;
-        peer_loop_finished_rx.next().await;
+    async fn peer_loop(
+        peer_uuid: PeerId,
+        handshake_meta: Self::HandshakeMeta,
+        stats_interval: Option<Duration>,
+        peer_stats_tx: UnboundedSender<(PeerId, PeerStats)>,
+    ) -> PeerId {
+        let (mut peer_loop_finished_rx, conn, data_channels) = handshake_meta;
+        let mut next_stats = stats_timer(stats_interval);
+        // Collected alongside the other futures, so the peer loop notices a disconnect meanwhile
+        let mut pending_stats = Fuse::terminated();
+
+        loop {
+            select! {
+                _ = peer_loop_finished_rx.next() => break,
+
+                _ = next_stats => {
+                    pending_stats = collect_stats(&conn, &data_channels).boxed_local().fuse();
+                }
+
+                stats = pending_stats => {
+                    if peer_stats_tx.unbounded_send((peer_uuid, stats)).is_err() {
+                        // should only happen if the socket is dropped
+                        warn!("failed to report stats for peer {peer_uuid}");
+                    }
+                    next_stats = stats_timer(stats_interval);
+                }
+            }
+        }
+
+        conn.close();
         peer_uuid
     }
 }
 
+async fn collect_stats(conn: &RtcPeerConnection, data_channels: &[RtcDataChannel]) -> PeerStats {
+    let mut channels: Vec<_> = data_channels
+        .iter()
+        .map(|data_channel| ChannelStats {
+            buffered_amount: data_channel.buffered_amount().into(),
+            ..Default::default()
+        })
+        .collect();
+
+    let report = match JsFuture::from(conn.get_stats()).await.efix() {
+        Ok(report) => report,
+        Err(err) => {
+            warn!("failed to get connection stats: {err}");
+            return PeerStats {
+                channels,
+                ..Default::default()
+            };
+        }
+    };
+
+    // The report is a maplike of stats objects, keyed by their id
+    let mut reports = HashMap::new();
+    js_sys::Map::unchecked_from_js(report).for_each(&mut |stats, id| {
+        if let Some(id) = id.as_string() {
+            reports.insert(id, stats);
+        }
+    });
+
+    let field = |stats: &JsValue, name: &str| {
+        Reflect::get(stats, &JsValue::from_str(name)).unwrap_or(JsValue::UNDEFINED)
+    };
+
+    let mut selected_pair_id = None;
+    let mut nominated_pair = None;
+    for report in reports.values() {
+        match field(report, "type").as_string().as_deref() {
+            Some("transport") => {
+                selected_pair_id = field(report, "selectedCandidatePairId").as_string();
+            }
+            Some("candidate-pair") => {
+                // Firefox marks the selected pair instead of reporting it on the transport
+                if field(report, "selected").as_bool() == Some(true) {
+                    selected_pair_id = field(report, "id").as_string();
+                } else if field(report, "nominated").as_bool() == Some(true)
+                    && field(report, "state").as_string().as_deref() == Some("succeeded")
+                {
+                    nominated_pair = Some(report);
+                }
+            }
+            Some("data-channel") => {
+                // Channels are negotiated with their index as id
+                let Some(stats) = field(report, "dataChannelIdentifier")
+                    .as_f64()
+                    .and_then(|id| channels.get_mut(id as usize))
+                else {
+                    continue;
+                };
+                let counter = |name: &str| field(report, name).as_f64().unwrap_or_default() as u64;
+                stats.bytes_sent = counter("bytesSent");
+                stats.bytes_received = counter("bytesReceived");
+                stats.packets_sent = counter("messagesSent");
+                stats.packets_received = counter("messagesReceived");
+            }
+            _ => {}
+        }
+    }
+
+    let Some(pair) = selected_pair_id
+        .and_then(|id| reports.get(&id))
+        .or(nominated_pair)
+    else {
+        return PeerStats {
+            channels,
+            ..Default::default()
+        };
+    };
+
+    let candidate_type = |id_field| {
+        let candidate = reports.get(&field(pair, id_field).as_string()?)?;
+        match field(candidate, "candidateType").as_string()?.as_str() {
+            "host" => Some(IceCandidateType::Host),
+            "srflx" => Some(IceCandidateType::ServerReflexive),
+            "prflx" => Some(IceCandidateType::PeerReflexive),
+            "relay" => Some(IceCandidateType::Relay),
+            _ => None,
+        }
+    };
+
+    PeerStats {
+        round_trip_time: field(pair, "currentRoundTripTime")
+            .as_f64()
+            .and_then(|rtt| Duration::try_from_secs_f64(rtt).ok()),
+        local_candidate_type: candidate_type("localCandidateId"),
+        remote_candidate_type: candidate_type("remoteCandidateId"),
+        channels,
+    }
+}
+
 async fn complete_handshake(
     signal_peer: SignalPeer,
     conn: RtcPeerConnection,
@@ -390,7 +535,7 @@ async fn try_add_rtc_ice_candidate(connection: &RtcPeerConnection, candidate_str
     .expect("failed to add ice candidate");
 }
 
-fn create_rtc_peer_connection(ice_server_config: &RtcIceServerConfig) -> RtcPeerConnection {
+fn create_rtc_peer_connection(ice_config: &IceConfig) -> RtcPeerConnection {
     #[derive(Serialize)]
     struct IceServerConfig {
         urls: Vec<String>,
@@ -399,13 +544,20 @@ fn create_rtc_peer_connection(ice_server_config: &RtcIceServerConfig) -> RtcPeer
     }
 
     let mut peer_config = RtcConfiguration::new();
-    let ice_server_config = IceServerConfig {
-        urls: ice_server_config.urls.clone(),
-        username: ice_server_config.username.clone().unwrap_or_default(),
-        credential: ice_server_config.credential.clone().unwrap_or_default(),
-    };
-    let ice_server_config_list = [ice_server_config];
+    let ice_server_config_list: Vec<_> = ice_config
+        .servers
+        .iter()
+        .map(|ice_server_config| IceServerConfig {
+            urls: ice_server_config.urls.clone(),
+            username: ice_server_config.username.clone().unwrap_or_default(),
+            credential: ice_server_config.credential.clone().unwrap_or_default(),
+        })
+        .collect();
     peer_config.ice_servers(&serde_wasm_bindgen::to_value(&ice_server_config_list).unwrap());
+    peer_config.ice_transport_policy(match ice_config.transport_policy {
+        RtcIceTransportPolicy::All => web_sys::RtcIceTransportPolicy::All,
+        RtcIceTransportPolicy::Relay => web_sys::RtcIceTransportPolicy::Relay,
+    });
     let connection = RtcPeerConnection::new_with_configuration(&peer_config).unwrap();
 
     let connection_1 = connection.clone();
@@ -504,6 +656,7 @@ fn create_data_channel(
         },
     );
 
+    let mut reassembler = channel_config.reassembler();
     leaking_channel_event_handler(
         |f| channel.set_onmessage(f),
         move |event: MessageEvent| {
@@ -512,7 +665,16 @@ fn create_data_channel(
                 let uarray = js_sys::Uint8Array::new(&arraybuf);
                 let body = uarray.to_vec();
 
-                if let Err(e) = incoming_tx.unbounded_send((peer_id, body.into_boxed_slice())) {
+                let packet = match reassembler.as_mut() {
+                    Some(reassembler) => match reassembler.push(&body) {
+                        Some(packet) => packet,
+                        // Wait for the remaining fragments
+                        None => return,
+                    },
+                    None => body.into_boxed_slice(),
+                };
+
+                if let Err(e) = incoming_tx.unbounded_send((peer_id, packet)) {
                     // should only happen if the socket is dropped, or we are out of memory
                     warn!("failed to notify about data channel message: {e:?}");
                 }